@@ -0,0 +1,9 @@
+#!/bin/sh
+# fetches the community chip-8-database into this folder, together with its license,
+# then rebuild to compile it into rust-8
+set -e
+cd "$(dirname "$0")"
+source=https://raw.githubusercontent.com/chip-8/chip-8-database/HEAD
+for file in database/sha1-hashes.json database/programs.json LICENSE; do
+    curl --fail --silent --show-error --location "$source/$file" --output "$(basename "$file")"
+done
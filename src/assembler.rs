@@ -0,0 +1,1189 @@
+// assembles a subset of Octo's language into a rom image loaded at 0x200. tokens are
+// separated by whitespace and `#` starts a comment. supported:
+//
+//   : name                     a label for the current address
+//   :next name                 a label for the second byte of the next instruction
+//   :const NAME value          a named number, defined before it is used
+//   :calc NAME { expression }  a named number worked out from numbers, constants and labels
+//   :alias NAME vX             another name for a register
+//   :macro name args { body }  `name a b` then stands for the body with its args swapped in
+//   :org addr                  carry on assembling at addr
+//   :byte value  :byte { expression }
+//   :unpack n addr  :unpack long addr   v0 := n << 4 | addr >> 8, v1 := addr & 0xFF
+//   :breakpoint name           ignored
+//   name                       calls the label `name`
+//   123 0x7B 0b1111011 -5      a byte of data, constants work too
+//   :call addr   jump addr   jump0 addr
+//   clear  return (or ;)  exit  lores  hires
+//   scroll-down n  scroll-up n  scroll-left  scroll-right
+//   vX := n|vY|random n|delay|key
+//   vX += n|vY   vX -= n|vY   vX =- vY
+//   vX |= vY   vX &= vY   vX ^= vY   vX >>= vY   vX <<= vY
+//   i := addr|long addr|hex vX|bighex vX   i += vX
+//   delay := vX  buzzer := vX  pitch := vX
+//   sprite vX vY n  bcd vX  save vX  load vX  save vX - vY  load vX - vY
+//   saveflags vX  loadflags vX  plane n  audio
+//   if vX <condition> then     the condition being == != < > <= >= n|vY, or key or -key
+//   if vX <condition> begin ... else ... end
+//   loop ... while vX <condition> ... again
+//
+// addresses can be numbers, constants or labels, and labels can be used before they are
+// defined. the < > <= >= comparisons work out the difference in vF, like octo does.
+
+use crate::chip8::PROGRAM_START;
+use crate::error::AssembleError;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy)]
+struct Token<'a> {
+    text: &'a str,
+    line: usize,
+    column: usize,
+}
+
+impl Token<'_> {
+    fn error(&self, message: impl Into<String>) -> AssembleError {
+        AssembleError {
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        }
+    }
+}
+
+// where an address goes in the instructions at some offset of the rom
+#[derive(Debug, Clone, Copy)]
+enum Operand {
+    // the low 12 bits of an opcode
+    Address,
+    // the 16 bit word after `i := long`
+    Long,
+    // the bytes of the `v0 := NN` and `v1 := NN` of `:unpack`, with the nibble on top of a
+    // 12 bit address, or a 16 bit one for `:unpack long`
+    Unpack(Option<u8>),
+}
+
+impl Operand {
+    fn max(&self) -> u16 {
+        match self {
+            Self::Address | Self::Unpack(Some(_)) => 0xFFF,
+            Self::Long | Self::Unpack(None) => 0xFFFF,
+        }
+    }
+}
+
+// a label used before it was defined, patched in once everything is assembled
+struct Fixup<'a> {
+    at: usize,
+    token: Token<'a>,
+    operand: Operand,
+}
+
+// an `if ... begin` or `loop` waiting for its `end` or `again`. `at` is the jump past the
+// body (or past the else part), filled in once we know where that ends.
+enum Block<'a> {
+    Branch {
+        token: Token<'a>,
+        at: usize,
+    },
+    Else {
+        token: Token<'a>,
+        at: usize,
+    },
+    Loop {
+        token: Token<'a>,
+        start: u16,
+        exits: Vec<usize>,
+    },
+}
+
+#[derive(Clone)]
+struct Macro<'a> {
+    parameters: Vec<&'a str>,
+    body: Vec<Token<'a>>,
+}
+
+// a macro that expands itself would otherwise never stop
+const MAX_EXPANSIONS: usize = 0x10000;
+
+struct Assembler<'a> {
+    tokens: Vec<Token<'a>>,
+    position: usize,
+    rom: Vec<u8>,
+    // where in `rom` the next byte goes, `:org` moves it around
+    here: usize,
+    labels: HashMap<&'a str, u16>,
+    // `:calc` can work out fractions, they're only rounded down when used
+    constants: HashMap<&'a str, f64>,
+    aliases: HashMap<&'a str, u8>,
+    macros: HashMap<&'a str, Macro<'a>>,
+    expansions: usize,
+    fixups: Vec<Fixup<'a>>,
+    blocks: Vec<Block<'a>>,
+}
+
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssembleError> {
+    let mut assembler = Assembler {
+        tokens: tokenize(source),
+        position: 0,
+        rom: Vec::new(),
+        here: 0,
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+        aliases: HashMap::new(),
+        macros: HashMap::new(),
+        expansions: 0,
+        fixups: Vec::new(),
+        blocks: Vec::new(),
+    };
+
+    while assembler.position < assembler.tokens.len() {
+        assembler.statement()?;
+    }
+    match assembler.blocks.last() {
+        Some(Block::Branch { token, .. } | Block::Else { token, .. }) => {
+            return Err(token.error("`if ... begin` is missing its `end`"));
+        }
+        Some(Block::Loop { token, .. }) => {
+            return Err(token.error("`loop` is missing its `again`"));
+        }
+        None => {}
+    }
+    assembler.resolve_fixups()?;
+    Ok(assembler.rom)
+}
+
+fn tokenize(source: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    for (line_number, line) in source.lines().enumerate() {
+        let code = line.split('#').next().unwrap_or("");
+        let mut start = None;
+        // a trailing space makes sure the last token on the line gets pushed
+        for (i, c) in code.char_indices().chain([(code.len(), ' ')]) {
+            match (c.is_whitespace(), start) {
+                (false, None) => start = Some(i),
+                (true, Some(token_start)) => {
+                    tokens.push(Token {
+                        text: &code[token_start..i],
+                        line: line_number + 1,
+                        column: code[..token_start].chars().count() + 1,
+                    });
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+    }
+    tokens
+}
+
+fn parse_number(text: &str) -> Option<i32> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i32::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i32::from_str_radix(binary, 2).ok()?
+    } else {
+        digits.parse().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn is_identifier(text: &str) -> bool {
+    text.chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && text
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+impl<'a> Assembler<'a> {
+    fn address(&self) -> u16 {
+        (PROGRAM_START + self.here) as u16
+    }
+
+    fn next(&mut self) -> Result<Token<'a>, AssembleError> {
+        match self.tokens.get(self.position) {
+            Some(&token) => {
+                self.position += 1;
+                Ok(token)
+            }
+            None => {
+                let last = self.tokens.last().copied().unwrap_or(Token {
+                    text: "",
+                    line: 1,
+                    column: 1,
+                });
+                Err(last.error("unexpected end of file"))
+            }
+        }
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.position).map(|token| token.text)
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), AssembleError> {
+        let token = self.next()?;
+        if token.text != text {
+            return Err(token.error(format!("expected `{text}`, found `{}`", token.text)));
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, opcode: u16) {
+        self.emit_bytes(&opcode.to_be_bytes());
+    }
+
+    fn emit_bytes(&mut self, bytes: &[u8]) {
+        let end = self.here + bytes.len();
+        if self.rom.len() < end {
+            self.rom.resize(end, 0);
+        }
+        self.rom[self.here..end].copy_from_slice(bytes);
+        self.here = end;
+    }
+
+    fn as_register(&self, text: &str) -> Option<u8> {
+        if let Some(&register) = self.aliases.get(text) {
+            return Some(register);
+        }
+        let digit = text.strip_prefix(['v', 'V'])?;
+        if digit.len() != 1 {
+            return None;
+        }
+        u8::from_str_radix(digit, 16).ok()
+    }
+
+    fn as_number(&self, text: &str) -> Option<i32> {
+        parse_number(text).or_else(|| Some(self.constants.get(text)?.floor() as i32))
+    }
+
+    fn register(&mut self) -> Result<u16, AssembleError> {
+        let token = self.next()?;
+        match self.as_register(token.text) {
+            Some(register) => Ok(register as u16),
+            None => Err(token.error(format!("expected a register, found `{}`", token.text))),
+        }
+    }
+
+    fn number(&mut self, min: i32, max: i32) -> Result<i32, AssembleError> {
+        let token = self.next()?;
+        self.check_number(token, min, max)
+    }
+
+    fn check_number(&self, token: Token, min: i32, max: i32) -> Result<i32, AssembleError> {
+        let Some(value) = self.as_number(token.text) else {
+            return Err(token.error(format!("expected a number, found `{}`", token.text)));
+        };
+        if !(min..=max).contains(&value) {
+            return Err(token.error(format!("{value} doesn't fit in {min}..{max}")));
+        }
+        Ok(value)
+    }
+
+    // a byte operand, negative numbers wrap around like they do in octo
+    fn byte(&mut self) -> Result<u16, AssembleError> {
+        Ok(self.number(-128, 255)? as u8 as u16)
+    }
+
+    fn nibble(&mut self) -> Result<u16, AssembleError> {
+        Ok(self.number(0, 15)? as u16)
+    }
+
+    // emits `opcode` with a 12 bit address, or a 16 bit one after it when `long`
+    fn emit_address(&mut self, opcode: u16, long: bool) -> Result<(), AssembleError> {
+        let at = self.here;
+        self.emit(opcode);
+        if long {
+            self.emit(0);
+        }
+        self.operand(
+            at,
+            if long {
+                Operand::Long
+            } else {
+                Operand::Address
+            },
+        )
+    }
+
+    // reads an address and fills it in as `operand` of the instructions at `at`, or leaves
+    // that for later when it's a label we haven't seen yet
+    fn operand(&mut self, at: usize, operand: Operand) -> Result<(), AssembleError> {
+        let token = self.next()?;
+        let value = match self.as_number(token.text) {
+            Some(_) => Some(self.check_number(token, 0, operand.max() as i32)? as u16),
+            None if is_identifier(token.text) => self.labels.get(token.text).copied(),
+            None => {
+                let message = format!("expected an address, found `{}`", token.text);
+                return Err(token.error(message));
+            }
+        };
+        match value {
+            Some(address) => self.patch(at, token, operand, address),
+            None => {
+                self.fixups.push(Fixup { at, token, operand });
+                Ok(())
+            }
+        }
+    }
+
+    fn patch(
+        &mut self,
+        at: usize,
+        token: Token,
+        operand: Operand,
+        address: u16,
+    ) -> Result<(), AssembleError> {
+        if address > operand.max() {
+            return Err(token.error(format!(
+                "`{}` is at 0x{address:04X}, out of reach of a 12 bit address",
+                token.text
+            )));
+        }
+        let [high, low] = address.to_be_bytes();
+        match operand {
+            Operand::Address => {
+                self.rom[at] |= high;
+                self.rom[at + 1] = low;
+            }
+            Operand::Long => self.rom[at + 2..at + 4].copy_from_slice(&[high, low]),
+            Operand::Unpack(nibble) => {
+                self.rom[at + 1] = nibble.unwrap_or(0) << 4 | high;
+                self.rom[at + 3] = low;
+            }
+        }
+        Ok(())
+    }
+
+    fn resolve_fixups(&mut self) -> Result<(), AssembleError> {
+        for fixup in std::mem::take(&mut self.fixups) {
+            let Some(&address) = self.labels.get(fixup.token.text) else {
+                return Err(fixup
+                    .token
+                    .error(format!("undefined label `{}`", fixup.token.text)));
+            };
+            self.patch(fixup.at, fixup.token, fixup.operand, address)?;
+        }
+        Ok(())
+    }
+
+    // points the jump at `at` to `target`, for the blocks `token` closes
+    fn patch_jump(&mut self, at: usize, token: Token, target: u16) -> Result<(), AssembleError> {
+        if target > 0xFFF {
+            return Err(token.error(format!(
+                "`{}` would jump to 0x{target:04X}, out of reach of a 12 bit address",
+                token.text
+            )));
+        }
+        let [high, low] = target.to_be_bytes();
+        self.rom[at] |= high;
+        self.rom[at + 1] = low;
+        Ok(())
+    }
+
+    // a name for a label, constant, alias or macro that isn't already taken
+    fn new_name(&mut self) -> Result<&'a str, AssembleError> {
+        let token = self.next()?;
+        if !is_identifier(token.text) || self.as_register(token.text).is_some() {
+            return Err(token.error(format!("`{}` can't be used as a name", token.text)));
+        }
+        if self.labels.contains_key(token.text)
+            || self.constants.contains_key(token.text)
+            || self.aliases.contains_key(token.text)
+            || self.macros.contains_key(token.text)
+        {
+            return Err(token.error(format!("`{}` is already defined", token.text)));
+        }
+        Ok(token.text)
+    }
+
+    fn statement(&mut self) -> Result<(), AssembleError> {
+        let token = self.next()?;
+        match token.text {
+            ":" => {
+                let name = self.new_name()?;
+                self.labels.insert(name, self.address());
+            }
+            ":next" => {
+                let name = self.new_name()?;
+                self.labels.insert(name, self.address().wrapping_add(1));
+            }
+            ":const" => {
+                let name = self.new_name()?;
+                let value = self.number(-0x8000, 0xFFFF)?;
+                self.constants.insert(name, value as f64);
+            }
+            ":calc" => {
+                let name = self.new_name()?;
+                self.expect("{")?;
+                let value = self.calc()?;
+                self.constants.insert(name, value);
+            }
+            ":alias" => {
+                let name = self.new_name()?;
+                let register = self.register()?;
+                self.aliases.insert(name, register as u8);
+            }
+            ":macro" => {
+                let name = self.new_name()?;
+                let mut parameters = Vec::new();
+                loop {
+                    let token = self.next()?;
+                    if token.text == "{" {
+                        break;
+                    }
+                    parameters.push(token.text);
+                }
+                let body = self.braced()?;
+                self.macros.insert(name, Macro { parameters, body });
+            }
+            ":org" => {
+                let address = self.number(PROGRAM_START as i32, 0xFFFF)?;
+                self.here = address as usize - PROGRAM_START;
+            }
+            ":byte" => {
+                let value = match self.peek() {
+                    Some("{") => {
+                        self.position += 1;
+                        // wrapped around like any other byte
+                        self.calc()?.floor() as i64 as u8
+                    }
+                    _ => self.byte()? as u8,
+                };
+                self.emit_bytes(&[value]);
+            }
+            ":unpack" => {
+                let nibble = match self.peek() {
+                    Some("long") => {
+                        self.position += 1;
+                        None
+                    }
+                    _ => Some(self.nibble()? as u8),
+                };
+                let at = self.here;
+                self.emit(0x6000);
+                self.emit(0x6100);
+                self.operand(at, Operand::Unpack(nibble))?;
+            }
+            ":breakpoint" => {
+                self.next()?;
+            }
+            ":call" => self.emit_address(0x2000, false)?,
+            "clear" => self.emit(0x00E0),
+            "return" | ";" => self.emit(0x00EE),
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(0x00C0 | n);
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit(0x00D0 | n);
+            }
+            "scroll-right" => self.emit(0x00FB),
+            "scroll-left" => self.emit(0x00FC),
+            "exit" => self.emit(0x00FD),
+            "lores" => self.emit(0x00FE),
+            "hires" => self.emit(0x00FF),
+            "jump" => self.emit_address(0x1000, false)?,
+            "jump0" => self.emit_address(0xB000, false)?,
+            "i" => self.index_operation()?,
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                let low = match token.text {
+                    "delay" => 0x15,
+                    "buzzer" => 0x18,
+                    _ => 0x3A,
+                };
+                self.emit(0xF000 | x << 8 | low);
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.emit(0xD000 | x << 8 | y << 4 | n);
+            }
+            "bcd" => {
+                let x = self.register()?;
+                self.emit(0xF033 | x << 8);
+            }
+            "save" | "load" => {
+                let x = self.register()?;
+                let save = token.text == "save";
+                if self.peek() == Some("-") {
+                    self.position += 1;
+                    let y = self.register()?;
+                    self.emit(if save { 0x5002 } else { 0x5003 } | x << 8 | y << 4);
+                } else {
+                    self.emit(if save { 0xF055 } else { 0xF065 } | x << 8);
+                }
+            }
+            "saveflags" => {
+                let x = self.register()?;
+                self.emit(0xF075 | x << 8);
+            }
+            "loadflags" => {
+                let x = self.register()?;
+                self.emit(0xF085 | x << 8);
+            }
+            "plane" => {
+                let n = self.nibble()?;
+                self.emit(0xF001 | n << 8);
+            }
+            "audio" => self.emit(0xF002),
+            "if" => self.if_statement(token)?,
+            "else" => match self.blocks.pop() {
+                Some(Block::Branch { at, .. }) => {
+                    let jump = self.here;
+                    self.emit(0x1000);
+                    self.patch_jump(at, token, self.address())?;
+                    self.blocks.push(Block::Else { token, at: jump });
+                }
+                _ => return Err(token.error("`else` without an `if ... begin`")),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::Branch { at, .. } | Block::Else { at, .. }) => {
+                    self.patch_jump(at, token, self.address())?;
+                }
+                _ => return Err(token.error("`end` without an `if ... begin`")),
+            },
+            "loop" => self.blocks.push(Block::Loop {
+                token,
+                start: self.address(),
+                exits: Vec::new(),
+            }),
+            "while" => {
+                // a while can sit inside an if, it belongs to the loop around both
+                if !self
+                    .blocks
+                    .iter()
+                    .any(|block| matches!(block, Block::Loop { .. }))
+                {
+                    return Err(token.error("`while` outside of a loop"));
+                }
+                self.condition(true)?;
+                let at = self.here;
+                self.emit(0x1000);
+                if let Some(Block::Loop { exits, .. }) = self
+                    .blocks
+                    .iter_mut()
+                    .rev()
+                    .find(|block| matches!(block, Block::Loop { .. }))
+                {
+                    exits.push(at);
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop { start, exits, .. }) => {
+                    let at = self.here;
+                    self.emit(0x1000);
+                    self.patch_jump(at, token, start)?;
+                    for exit in exits {
+                        self.patch_jump(exit, token, self.address())?;
+                    }
+                }
+                _ => return Err(token.error("`again` without a `loop`")),
+            },
+            text => {
+                if let Some(body) = self.macros.get(text).cloned() {
+                    self.expand(token, body)?;
+                } else if let Some(x) = self.as_register(text) {
+                    self.register_operation(x as u16)?;
+                } else if self.as_number(text).is_some() {
+                    let byte = self.check_number(token, -128, 255)?;
+                    self.emit_bytes(&[byte as u8]);
+                } else if is_identifier(text) {
+                    // a bare label name calls it
+                    self.position -= 1;
+                    self.emit_address(0x2000, false)?;
+                } else if text.starts_with(':') {
+                    return Err(token.error(format!("`{text}` isn't a supported directive")));
+                } else {
+                    return Err(token.error(format!("unknown statement `{text}`")));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn index_operation(&mut self) -> Result<(), AssembleError> {
+        let operator = self.next()?;
+        match operator.text {
+            ":=" => match self.peek() {
+                Some("long") => {
+                    self.position += 1;
+                    self.emit_address(0xF000, true)
+                }
+                Some("hex") => {
+                    self.position += 1;
+                    let x = self.register()?;
+                    self.emit(0xF029 | x << 8);
+                    Ok(())
+                }
+                Some("bighex") => {
+                    self.position += 1;
+                    let x = self.register()?;
+                    self.emit(0xF030 | x << 8);
+                    Ok(())
+                }
+                _ => self.emit_address(0xA000, false),
+            },
+            "+=" => {
+                let x = self.register()?;
+                self.emit(0xF01E | x << 8);
+                Ok(())
+            }
+            text => Err(operator.error(format!("`i {text}` isn't supported"))),
+        }
+    }
+
+    fn register_operation(&mut self, x: u16) -> Result<(), AssembleError> {
+        let operator = self.next()?;
+        let operand = self.peek().and_then(|text| self.as_register(text));
+        let opcode = match (operator.text, operand) {
+            (":=", Some(y)) => 0x8000 | (y as u16) << 4,
+            ("|=", Some(y)) => 0x8001 | (y as u16) << 4,
+            ("&=", Some(y)) => 0x8002 | (y as u16) << 4,
+            ("^=", Some(y)) => 0x8003 | (y as u16) << 4,
+            ("+=", Some(y)) => 0x8004 | (y as u16) << 4,
+            ("-=", Some(y)) => 0x8005 | (y as u16) << 4,
+            (">>=", Some(y)) => 0x8006 | (y as u16) << 4,
+            ("=-", Some(y)) => 0x8007 | (y as u16) << 4,
+            ("<<=", Some(y)) => 0x800E | (y as u16) << 4,
+            (":=", None) => {
+                match self.peek() {
+                    Some("random") => {
+                        self.position += 1;
+                        let nn = self.byte()?;
+                        self.emit(0xC000 | x << 8 | nn);
+                    }
+                    Some("delay") => {
+                        self.position += 1;
+                        self.emit(0xF007 | x << 8);
+                    }
+                    Some("key") => {
+                        self.position += 1;
+                        self.emit(0xF00A | x << 8);
+                    }
+                    _ => {
+                        let nn = self.byte()?;
+                        self.emit(0x6000 | x << 8 | nn);
+                    }
+                }
+                return Ok(());
+            }
+            ("+=", None) => {
+                let nn = self.byte()?;
+                self.emit(0x7000 | x << 8 | nn);
+                return Ok(());
+            }
+            ("-=", None) => {
+                // there is no subtract immediate, so add the negated byte instead
+                let nn = (self.byte()? as u8).wrapping_neg();
+                self.emit(0x7000 | x << 8 | nn as u16);
+                return Ok(());
+            }
+            ("|=" | "&=" | "^=" | ">>=" | "=-" | "<<=", None) => {
+                let token = self.next()?;
+                let message = format!("`{}` only works between registers", operator.text);
+                return Err(token.error(message));
+            }
+            (text, _) => {
+                return Err(operator.error(format!("`{text}` isn't a register operation")));
+            }
+        };
+
+        // the register operand is only consumed once we know it belongs to the operator
+        self.position += 1;
+        self.emit(opcode | x << 8);
+        Ok(())
+    }
+
+    // the tokens up to the `}` matching a `{` we've just read, which is skipped
+    fn braced(&mut self) -> Result<Vec<Token<'a>>, AssembleError> {
+        let mut body = Vec::new();
+        let mut depth = 0;
+        loop {
+            let token = self.next()?;
+            match token.text {
+                "{" => depth += 1,
+                "}" if depth == 0 => return Ok(body),
+                "}" => depth -= 1,
+                _ => {}
+            }
+            body.push(token);
+        }
+    }
+
+    // puts the macro's body in place of its use, with the arguments that follow the name
+    fn expand(&mut self, name: Token<'a>, body: Macro<'a>) -> Result<(), AssembleError> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            let message = format!("`{}` keeps expanding, is it recursive?", name.text);
+            return Err(name.error(message));
+        }
+        let mut arguments = HashMap::new();
+        for parameter in body.parameters {
+            arguments.insert(parameter, self.next()?.text);
+        }
+        let expanded: Vec<Token<'a>> = body
+            .body
+            .iter()
+            .map(|token| Token {
+                text: arguments.get(token.text).copied().unwrap_or(token.text),
+                ..*token
+            })
+            .collect();
+        self.tokens.splice(self.position..self.position, expanded);
+        Ok(())
+    }
+
+    // the expression of a `:calc` or `:byte { }`, up to and including the closing `}`. like
+    // octo, operators have no precedence and group from the right, so `1 - 2 - 3` is 2
+    fn calc(&mut self) -> Result<f64, AssembleError> {
+        let value = self.calc_expression()?;
+        self.expect("}")?;
+        Ok(value)
+    }
+
+    fn calc_expression(&mut self) -> Result<f64, AssembleError> {
+        let left = self.calc_term()?;
+        if matches!(self.peek(), Some("}" | ")") | None) {
+            return Ok(left);
+        }
+        let operator = self.next()?;
+        let right = self.calc_expression()?;
+        let (a, b) = (left as i64, right as i64);
+        Ok(match operator.text {
+            "+" => left + right,
+            "-" => left - right,
+            "*" => left * right,
+            "/" => left / right,
+            "%" => left % right,
+            "pow" => left.powf(right),
+            "min" => left.min(right),
+            "max" => left.max(right),
+            "&" => (a & b) as f64,
+            "|" => (a | b) as f64,
+            "^" => (a ^ b) as f64,
+            "<<" => a.wrapping_shl(b as u32) as f64,
+            ">>" => a.wrapping_shr(b as u32) as f64,
+            "<" => (left < right) as u8 as f64,
+            ">" => (left > right) as u8 as f64,
+            "<=" => (left <= right) as u8 as f64,
+            ">=" => (left >= right) as u8 as f64,
+            "==" => (left == right) as u8 as f64,
+            "!=" => (left != right) as u8 as f64,
+            text => return Err(operator.error(format!("`{text}` isn't a calc operator"))),
+        })
+    }
+
+    fn calc_term(&mut self) -> Result<f64, AssembleError> {
+        let token = self.next()?;
+        let value = match token.text {
+            "(" => {
+                let value = self.calc_expression()?;
+                self.expect(")")?;
+                value
+            }
+            "-" => -self.calc_term()?,
+            "~" => !(self.calc_term()? as i64) as f64,
+            "!" => (self.calc_term()? == 0.0) as u8 as f64,
+            "sin" => self.calc_term()?.sin(),
+            "cos" => self.calc_term()?.cos(),
+            "tan" => self.calc_term()?.tan(),
+            "exp" => self.calc_term()?.exp(),
+            "log" => self.calc_term()?.ln(),
+            "abs" => self.calc_term()?.abs(),
+            "sqrt" => self.calc_term()?.sqrt(),
+            "ceil" => self.calc_term()?.ceil(),
+            "floor" => self.calc_term()?.floor(),
+            "sign" => {
+                let value = self.calc_term()?;
+                if value == 0.0 {
+                    0.0
+                } else {
+                    value.signum()
+                }
+            }
+            "HERE" => self.address() as f64,
+            "PI" => std::f64::consts::PI,
+            "E" => std::f64::consts::E,
+            text => {
+                let value = parse_number(text)
+                    .map(f64::from)
+                    .or_else(|| self.constants.get(text).copied())
+                    .or_else(|| self.labels.get(text).map(|&address| address as f64));
+                match value {
+                    Some(value) => value,
+                    None if is_identifier(text) => {
+                        return Err(token.error(format!("`{text}` isn't defined yet")));
+                    }
+                    None => return Err(token.error(format!("expected a number, found `{text}`"))),
+                }
+            }
+        };
+        Ok(value)
+    }
+
+    // `if vX <condition> then` skips the next statement when the condition is false, and
+    // `if vX <condition> begin` jumps past its block, skipping that jump when it's true
+    fn if_statement(&mut self, token: Token<'a>) -> Result<(), AssembleError> {
+        let operator = self.tokens.get(self.position + 1).map(|token| token.text);
+        let keyword = self.position
+            + if matches!(operator, Some("key" | "-key")) {
+                2
+            } else {
+                3
+            };
+        if self.tokens.get(keyword).map(|token| token.text) == Some("begin") {
+            self.condition(true)?;
+            self.position += 1;
+            let at = self.here;
+            self.emit(0x1000);
+            self.blocks.push(Block::Branch { token, at });
+            return Ok(());
+        }
+
+        self.condition(false)?;
+        let keyword = self.next()?;
+        if keyword.text != "then" {
+            let message = format!("expected `then` or `begin`, found `{}`", keyword.text);
+            return Err(keyword.error(message));
+        }
+        Ok(())
+    }
+
+    // `vX <condition>`, assembled to the skip that jumps over the next instruction when the
+    // condition is false, or when it's true if `negated`. the comparisons subtract in vF
+    // and then skip on its borrow flag.
+    fn condition(&mut self, negated: bool) -> Result<(), AssembleError> {
+        let x = self.register()?;
+        let condition = self.next()?;
+        let text = match (negated, condition.text) {
+            (false, text) => text,
+            (true, "==") => "!=",
+            (true, "!=") => "==",
+            (true, "key") => "-key",
+            (true, "-key") => "key",
+            (true, ">") => "<=",
+            (true, "<") => ">=",
+            (true, ">=") => "<",
+            (true, "<=") => ">",
+            (true, text) => text,
+        };
+        match text {
+            "key" => self.emit(0xE0A1 | x << 8),
+            "-key" => self.emit(0xE09E | x << 8),
+            "==" | "!=" => {
+                let equals = text == "==";
+                let opcode = match self.peek().and_then(|text| self.as_register(text)) {
+                    Some(y) => {
+                        self.position += 1;
+                        (if equals { 0x9000 } else { 0x5000 }) | (y as u16) << 4
+                    }
+                    None => (if equals { 0x4000 } else { 0x3000 }) | self.byte()?,
+                };
+                self.emit(opcode | x << 8);
+            }
+            ">" | "<" | ">=" | "<=" => {
+                let load = match self.peek().and_then(|text| self.as_register(text)) {
+                    Some(y) => {
+                        self.position += 1;
+                        0x8F00 | (y as u16) << 4
+                    }
+                    None => 0x6F00 | self.byte()?,
+                };
+                self.emit(load);
+                // vF := n - vX for > and <=, vX - n for < and >=, leaving 1 unless it borrowed
+                let subtract = if matches!(text, ">" | "<=") {
+                    0x8F05
+                } else {
+                    0x8F07
+                };
+                self.emit(subtract | x << 4);
+                self.emit(if matches!(text, ">" | "<") {
+                    0x3F01
+                } else {
+                    0x4F01
+                });
+            }
+            _ => {
+                let message = format!("`{}` isn't a supported condition", condition.text);
+                return Err(condition.error(message));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::{disassemble_rom, Syntax};
+    use crate::{Chip8, Platform, Quirks};
+
+    fn words(source: &str) -> Vec<u16> {
+        let rom = assemble(source).unwrap_or_else(|err| panic!("{source}: {err}"));
+        rom.chunks(2)
+            .map(|word| u16::from_be_bytes([word[0], *word.get(1).unwrap_or(&0)]))
+            .collect()
+    }
+
+    fn error(source: &str) -> (usize, usize, String) {
+        let err = assemble(source).expect_err(source);
+        (err.line, err.column, err.message)
+    }
+
+    #[test]
+    fn assembles_every_statement_form() {
+        let cases: &[(&str, &[u16])] = &[
+            ("clear", &[0x00E0]),
+            ("return", &[0x00EE]),
+            (";", &[0x00EE]),
+            ("scroll-down 3", &[0x00C3]),
+            ("scroll-up 4", &[0x00D4]),
+            ("scroll-right scroll-left", &[0x00FB, 0x00FC]),
+            ("exit lores hires", &[0x00FD, 0x00FE, 0x00FF]),
+            ("jump 0x345", &[0x1345]),
+            ("jump0 0x345", &[0xB345]),
+            (":call 0x345", &[0x2345]),
+            ("v3 := 0x42", &[0x6342]),
+            ("v3 := -1", &[0x63FF]),
+            ("v3 := v4", &[0x8340]),
+            ("v3 |= v4 v3 &= v4 v3 ^= v4", &[0x8341, 0x8342, 0x8343]),
+            ("v3 += v4 v3 -= v4 v3 >>= v4", &[0x8344, 0x8345, 0x8346]),
+            ("v3 =- v4 v3 <<= v4", &[0x8347, 0x834E]),
+            ("v3 += 5", &[0x7305]),
+            ("v3 -= 5", &[0x73FB]),
+            ("v3 := random 0x0F", &[0xC30F]),
+            ("v3 := delay v3 := key", &[0xF307, 0xF30A]),
+            ("i := 0x345", &[0xA345]),
+            ("i := long 0x1234", &[0xF000, 0x1234]),
+            ("i := hex v3 i := bighex v3", &[0xF329, 0xF330]),
+            ("i += v3", &[0xF31E]),
+            (
+                "delay := v3 buzzer := v3 pitch := v3",
+                &[0xF315, 0xF318, 0xF33A],
+            ),
+            ("sprite v1 v2 5", &[0xD125]),
+            ("bcd v3", &[0xF333]),
+            ("save v3 load v3", &[0xF355, 0xF365]),
+            ("save v3 - v5 load v3 - v5", &[0x5352, 0x5353]),
+            ("saveflags v3 loadflags v3", &[0xF375, 0xF385]),
+            ("plane 3 audio", &[0xF301, 0xF002]),
+            ("0x12 0b00110100", &[0x1234]),
+            (":const SPEED 7 v0 := SPEED", &[0x6007]),
+            (":alias px v5 px += 1", &[0x7501]),
+            (": main jump main", &[0x1200]),
+            (": main main", &[0x2200]),
+            (":byte 0x12 :byte { 0x30 + 4 }", &[0x1234]),
+            (":next target v0 := 0 i := target", &[0x6000, 0xA201]),
+            (
+                ":unpack 0xA 0x345 :unpack long 0x1234",
+                &[0x60A3, 0x6145, 0x6012, 0x6134],
+            ),
+            (
+                ":org 0x204 clear :org 0x200 jump 0x204",
+                &[0x1204, 0x0000, 0x00E0],
+            ),
+            (":breakpoint here clear", &[0x00E0]),
+            (
+                ":macro twice X { X += 1 X += 1 } twice v3",
+                &[0x7301, 0x7301],
+            ),
+            (
+                ":calc A { 3 / 2 } :calc B { A * 2 } :byte B :byte { 1 - 2 - 3 }",
+                &[0x0302],
+            ),
+            (":calc C { ( 1 - 2 ) - 3 } v0 := C", &[0x60FC]),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(words(source), *expected, "{source}");
+        }
+    }
+
+    #[test]
+    fn labels_can_be_used_before_they_are_defined() {
+        let source = "
+            jump later
+            :call later
+            i := later
+            i := long later
+            :unpack 0x1 later
+            : later
+            clear
+        ";
+        assert_eq!(
+            words(source),
+            [0x120E, 0x220E, 0xA20E, 0xF000, 0x020E, 0x6012, 0x610E, 0x00E0]
+        );
+        assert_eq!(
+            error("jump nowhere"),
+            (1, 6, "undefined label `nowhere`".to_string())
+        );
+    }
+
+    #[test]
+    fn if_then_skips_when_the_condition_is_false() {
+        let cases: &[(&str, &[u16])] = &[
+            ("if v1 == 5 then", &[0x4105]),
+            ("if v1 != 5 then", &[0x3105]),
+            ("if v1 == v2 then", &[0x9120]),
+            ("if v1 != v2 then", &[0x5120]),
+            ("if v1 key then", &[0xE1A1]),
+            ("if v1 -key then", &[0xE19E]),
+            ("if v1 > 5 then", &[0x6F05, 0x8F15, 0x3F01]),
+            ("if v1 < v2 then", &[0x8F20, 0x8F17, 0x3F01]),
+            ("if v1 >= 5 then", &[0x6F05, 0x8F17, 0x4F01]),
+            ("if v1 <= 5 then", &[0x6F05, 0x8F15, 0x4F01]),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(words(source), *expected, "{source}");
+        }
+    }
+
+    #[test]
+    fn comparisons_hold_when_run() {
+        let cases = [
+            ("<", 3, 5),
+            ("<", 5, 5),
+            ("<", 5, 3),
+            (">", 3, 5),
+            (">", 5, 5),
+        ];
+        let cases = cases
+            .into_iter()
+            .chain([(">", 5, 3), ("<=", 3, 5), ("<=", 5, 5)]);
+        let cases = cases.chain([("<=", 5, 3), (">=", 3, 5), (">=", 5, 5), (">=", 5, 3)]);
+        for (operator, a, b) in cases {
+            let expected = match operator {
+                "<" => a < b,
+                ">" => a > b,
+                "<=" => a <= b,
+                _ => a >= b,
+            };
+            // against a register and a byte, skipping a statement and a block
+            let sources = [
+                format!("v0 := {a} v1 := {b} if v0 {operator} v1 then v2 := 1"),
+                format!("v0 := {a} if v0 {operator} {b} then v2 := 1"),
+                format!("v0 := {a} v1 := {b} if v0 {operator} v1 begin v2 := 1 end"),
+                format!("v0 := {a} if v0 {operator} {b} begin v2 := 1 else v3 := 1 end"),
+            ];
+            for source in sources {
+                let rom = assemble(&format!("{source} : stop jump stop")).unwrap();
+                let mut chip8 = Chip8::new(Platform::Chip8, Quirks::octo());
+                chip8.load_program(&rom).unwrap();
+                for _ in 0..16 {
+                    chip8.tick().unwrap();
+                }
+                assert_eq!(chip8.get_registers()[2] == 1, expected, "{source}");
+            }
+        }
+    }
+
+    #[test]
+    fn blocks_jump_over_their_bodies() {
+        // begin skips the jump past the block when the condition holds
+        assert_eq!(
+            words("if v0 == 1 begin v1 := 2 end"),
+            [0x3001, 0x1206, 0x6102]
+        );
+        assert_eq!(
+            words("if v0 == 1 begin v1 := 2 else v1 := 3 end"),
+            [0x3001, 0x1208, 0x6102, 0x120A, 0x6103]
+        );
+        assert_eq!(words("if v0 key begin clear end"), [0xE09E, 0x1206, 0x00E0]);
+        assert_eq!(
+            words("loop v0 += 1 while v0 != 10 again"),
+            [0x7001, 0x400A, 0x1208, 0x1200]
+        );
+        // a while inside an if leaves the loop around it
+        assert_eq!(
+            words("loop if v0 key begin while v1 == 0 end again"),
+            [0xE09E, 0x1208, 0x3100, 0x120A, 0x1200]
+        );
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_token() {
+        assert_eq!(
+            error("clear\n  v0 := 300"),
+            (2, 9, "300 doesn't fit in -128..255".to_string())
+        );
+        assert_eq!(
+            error("v0 := v1\nfrobnicate!"),
+            (2, 1, "unknown statement `frobnicate!`".to_string())
+        );
+        assert_eq!(
+            error("  :stringmode x \"abc\" { }"),
+            (
+                1,
+                3,
+                "`:stringmode` isn't a supported directive".to_string()
+            )
+        );
+        assert_eq!(
+            error("if v0 == 1\n clear"),
+            (
+                2,
+                2,
+                "expected `then` or `begin`, found `clear`".to_string()
+            )
+        );
+        assert_eq!(
+            error("if v0 ~ 1 then"),
+            (1, 7, "`~` isn't a supported condition".to_string())
+        );
+        assert_eq!(
+            error("clear\n loop\n clear"),
+            (2, 2, "`loop` is missing its `again`".to_string())
+        );
+        assert_eq!(
+            error("end"),
+            (1, 1, "`end` without an `if ... begin`".to_string())
+        );
+        assert_eq!(
+            error("i |= v0"),
+            (1, 3, "`i |=` isn't supported".to_string())
+        );
+        assert_eq!(
+            error(": main : main"),
+            (1, 10, "`main` is already defined".to_string())
+        );
+        assert_eq!(
+            error(":org 0x1000 : far jump far"),
+            (
+                1,
+                24,
+                "`far` is at 0x1000, out of reach of a 12 bit address".to_string()
+            )
+        );
+        assert_eq!(
+            error("v0 := 1\nsprite v0 v1"),
+            (2, 11, "unexpected end of file".to_string())
+        );
+    }
+
+    #[test]
+    fn reassembles_what_the_disassembler_prints() {
+        // every opcode there is, unknown ones come back as data bytes
+        let rom: Vec<u8> = (0..=0xFFFFu16).flat_map(u16::to_be_bytes).collect();
+        let listing = disassemble_rom(&rom, Syntax::Octo, false);
+        let source: String = listing
+            .lines()
+            .map(|line| {
+                // the address and raw bytes come first
+                let text: Vec<&str> = line.split_whitespace().skip(2).collect();
+                text.join(" ") + "\n"
+            })
+            .collect();
+        assert_eq!(assemble(&source).unwrap(), rom);
+    }
+}
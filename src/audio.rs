@@ -0,0 +1,156 @@
+use rust_8::{AudioSink, Chip8};
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use std::f32::consts::TAU;
+
+const SAMPLE_RATE: i32 = 44100;
+// how long the volume takes to fade in or out, cutting a wave off mid-cycle clicks
+const RAMP_SECONDS: f32 = 0.005;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+    Square,
+    Triangle,
+    Sawtooth,
+    Sine,
+}
+
+impl Waveform {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "square" => Some(Self::Square),
+            "triangle" => Some(Self::Triangle),
+            "sawtooth" | "saw" => Some(Self::Sawtooth),
+            "sine" => Some(Self::Sine),
+            _ => None,
+        }
+    }
+
+    // `phase` goes from 0 to 1 over one cycle
+    fn sample(&self, phase: f32) -> f32 {
+        match self {
+            Self::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Self::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Self::Sawtooth => 2.0 * phase - 1.0,
+            Self::Sine => (TAU * phase).sin(),
+        }
+    }
+}
+
+// the buzzer played while the sound timer runs, unless an XO-CHIP pattern is loaded
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tone {
+    pub frequency: f32,
+    // 0 to 1
+    pub volume: f32,
+    pub waveform: Waveform,
+}
+
+impl Default for Tone {
+    fn default() -> Self {
+        Self {
+            frequency: 440.0,
+            volume: 0.25,
+            waveform: Waveform::Square,
+        }
+    }
+}
+
+struct Wave {
+    tone: Tone,
+    sample_rate: f32,
+    // XO-CHIP's 1-bit audio pattern, looped over its 128 bits
+    pattern: Option<[u8; 16]>,
+    // how many pattern bits we move forward for each output sample
+    step: f32,
+    position: f32,
+    // how far through a cycle of the tone we are, from 0 to 1
+    phase: f32,
+    playing: bool,
+    // fades towards 1 while playing and back to 0 after, the wave keeps going meanwhile
+    gain: f32,
+}
+
+impl AudioCallback for Wave {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        let ramp = 1.0 / (RAMP_SECONDS * self.sample_rate);
+        for sample in out.iter_mut() {
+            self.gain = match self.playing {
+                true => (self.gain + ramp).min(1.0),
+                false => (self.gain - ramp).max(0.0),
+            };
+            if self.gain == 0.0 {
+                *sample = 0.0;
+                continue;
+            }
+
+            let value = match self.pattern {
+                Some(pattern) => {
+                    let bit = self.position as usize;
+                    self.position = (self.position + self.step) % 128.0;
+                    if (pattern[bit / 8] >> (7 - bit % 8)) & 1 == 1 {
+                        1.0
+                    } else {
+                        -1.0
+                    }
+                }
+                None => {
+                    let value = self.tone.waveform.sample(self.phase);
+                    self.phase = (self.phase + self.tone.frequency / self.sample_rate) % 1.0;
+                    value
+                }
+            };
+            *sample = value * self.tone.volume * self.gain;
+        }
+    }
+}
+
+pub struct Audio {
+    device: AudioDevice<Wave>,
+}
+
+impl Audio {
+    pub fn new(sdl_context: &sdl2::Sdl, tone: Tone) -> Self {
+        let audio_subsystem = sdl_context.audio().unwrap();
+        let desired_spec = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE),
+            channels: Some(1),
+            samples: None,
+        };
+
+        let device = audio_subsystem
+            .open_playback(None, &desired_spec, |spec| Wave {
+                tone,
+                sample_rate: spec.freq as f32,
+                pattern: None,
+                step: 0.0,
+                position: 0.0,
+                phase: 0.0,
+                playing: false,
+                gain: 0.0,
+            })
+            .unwrap();
+        device.resume();
+
+        Audio { device }
+    }
+}
+
+impl AudioSink for Audio {
+    fn update(&mut self, chip8: &Chip8) {
+        let mut wave = self.device.lock();
+        wave.playing = chip8.get_sound_timer() > 0;
+        // the last sound keeps playing while it fades out
+        if wave.playing {
+            wave.pattern = chip8.get_audio_pattern().copied();
+            wave.step = chip8.get_audio_rate() / wave.sample_rate;
+        }
+    }
+}
@@ -0,0 +1,238 @@
+// runs a rom without opening a window, then dumps the screen. meant for automated checks:
+//
+//   rust-8-headless <rom> --frames 120 --press 30:5:4 --output screen.pbm
+//
+// --press <frame>:<key>[:<frames held>] can be repeated, keys are given in hex like on the
+// keypad. the screen goes to stdout as ascii art unless --output names a .pbm file.
+
+use rust_8::database::RomDatabase;
+use rust_8::frontend::{NoAudio, DEFAULT_TICKRATE};
+use rust_8::rng::{Generator, VipRandom};
+use rust_8::{Chip8, Command, DisplaySink, InputSource, Platform, Quirks, Rom, Runner};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+struct KeyPress {
+    frame: u32,
+    key: usize,
+    held_for: u32,
+}
+
+impl KeyPress {
+    fn parse(spec: &str) -> Option<Self> {
+        let mut parts = spec.split(':');
+        let frame = parts.next()?.parse().ok()?;
+        let key = usize::from_str_radix(parts.next()?, 16).ok()?;
+        let held_for = match parts.next() {
+            Some(frames) => frames.parse().ok()?,
+            None => 2,
+        };
+        if key > 0xF || parts.next().is_some() {
+            return None;
+        }
+        Some(Self {
+            frame,
+            key,
+            held_for,
+        })
+    }
+}
+
+// plays back the scripted key presses and keeps a copy of the last frame drawn
+struct Headless {
+    frame: u32,
+    frames: u32,
+    presses: Vec<KeyPress>,
+    buffer: Vec<u8>,
+    width: usize,
+    height: usize,
+}
+
+impl InputSource for Headless {
+    fn process_input(&mut self, keypad: &mut [bool; 16]) -> Vec<Command> {
+        if self.frame == self.frames {
+            return vec![Command::Quit];
+        }
+
+        keypad.fill(false);
+        for press in &self.presses {
+            if (press.frame..press.frame + press.held_for).contains(&self.frame) {
+                keypad[press.key] = true;
+            }
+        }
+
+        self.frame += 1;
+        Vec::new()
+    }
+}
+
+impl DisplaySink for Headless {
+    fn draw(&mut self, buffer: &[u8], width: usize, height: usize) {
+        self.buffer = buffer.to_vec();
+        self.width = width;
+        self.height = height;
+    }
+}
+
+impl Headless {
+    // plain (P1) pbm, where 1 is a lit pixel on any plane
+    fn to_pbm(&self) -> String {
+        let mut out = format!("P1\n{} {}\n", self.width, self.height);
+        for row in self.buffer.chunks(self.width) {
+            let line: Vec<&str> = row
+                .iter()
+                .map(|pixel| if *pixel != 0 { "1" } else { "0" })
+                .collect();
+            out.push_str(&line.join(" "));
+            out.push('\n');
+        }
+        out
+    }
+
+    fn to_ascii(&self) -> String {
+        let mut out = String::new();
+        for row in self.buffer.chunks(self.width) {
+            // one character per plane combination: off, plane 1, plane 2, both
+            out.extend(
+                row.iter()
+                    .map(|pixel| [' ', '#', '+', '@'][(*pixel & 0x3) as usize]),
+            );
+            out.push('\n');
+        }
+        out
+    }
+}
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: rust-8-headless <rom> [--frames N] [--platform P] [--quirks Q] \
+         [--display-wait on|off] [--seed N] [--rng vip|xorshift] [--vip-rom FILE] [--rom-db DIR] \
+         [--press FRAME:KEY[:HELD]]... [--output FILE]"
+    );
+    process::exit(2);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut rom_path = None;
+    let mut frames = 60;
+    let mut platform = None;
+    let mut quirks = None;
+    let mut seed = None;
+    let mut generator = None;
+    let mut vip_rom = None;
+    let mut rom_db = None;
+    let mut display_wait = None;
+    let mut presses = Vec::new();
+    let mut output = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--frames" => frames = value().parse().unwrap_or_else(|_| usage()),
+            "--platform" => {
+                platform = Some(Platform::from_name(value()).unwrap_or_else(|| usage()))
+            }
+            "--quirks" => quirks = Some(Quirks::from_name(value()).unwrap_or_else(|| usage())),
+            "--seed" => seed = Some(value().parse().unwrap_or_else(|_| usage())),
+            "--display-wait" => {
+                display_wait = match value().as_str() {
+                    "on" => Some(true),
+                    "off" => Some(false),
+                    _ => usage(),
+                }
+            }
+            "--rng" => generator = Some(Generator::from_name(value()).unwrap_or_else(|| usage())),
+            "--vip-rom" => vip_rom = Some(value().clone()),
+            "--rom-db" => rom_db = Some(value().clone()),
+            "--press" => presses.push(KeyPress::parse(value()).unwrap_or_else(|| usage())),
+            "--output" => output = Some(value().clone()),
+            _ if rom_path.is_none() && !arg.starts_with("--") => rom_path = Some(arg.clone()),
+            _ => usage(),
+        }
+    }
+    let rom_path = rom_path.unwrap_or_else(|| usage());
+    // a dump of the interpreter is only any use to the VIP's generator
+    if vip_rom.is_some() && generator == Some(Generator::Xorshift) {
+        usage();
+    }
+    let rom = Rom::read(&rom_path).unwrap_or_else(|err| {
+        eprintln!("Couldn't load {rom_path}: {err}");
+        process::exit(1);
+    });
+
+    let database = match rom_db {
+        Some(folder) => RomDatabase::load(Path::new(&folder)).unwrap_or_else(|err| {
+            eprintln!("Couldn't load the rom database in {folder}: {err}");
+            process::exit(1);
+        }),
+        None => RomDatabase::bundled(),
+    };
+    // the same as the other frontends: the command line, then the rom's own options, then
+    // the rom database's
+    let options = match database.lookup(&rom.program) {
+        Some(entry) => rom.options.or(entry.options),
+        None => rom.options,
+    };
+    let (platform, quirks) = options.machine(platform, quirks);
+    let quirks = Quirks {
+        display_wait: display_wait.unwrap_or(quirks.display_wait),
+        ..quirks
+    };
+    let mut chip8 = Chip8::new(platform, quirks);
+    if let Some(path) = vip_rom {
+        let rng = VipRandom::load(rand::random(), Path::new(&path)).unwrap_or_else(|err| {
+            eprintln!("Couldn't load the VIP interpreter in {path}: {err}");
+            process::exit(1);
+        });
+        chip8.set_random_source(Box::new(rng));
+    } else if let Some(generator) = generator {
+        chip8.set_random_source(generator.source(rand::random()));
+    }
+    if let Some(seed) = seed {
+        chip8.seed_random(seed);
+    }
+    if let Err(err) = chip8.load_program(&rom.program) {
+        eprintln!("Couldn't load {rom_path}: {err}");
+        process::exit(1);
+    }
+
+    let (width, height) = chip8.get_resolution();
+    let mut headless = Headless {
+        frame: 0,
+        frames,
+        presses,
+        buffer: chip8.get_video_buffer().to_vec(),
+        width,
+        height,
+    };
+
+    let mut runner = Runner::new(options.tickrate.unwrap_or(DEFAULT_TICKRATE));
+    let mut status = 0;
+    loop {
+        match runner.run_frame(&mut chip8, &mut headless, &mut NoAudio) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(err) => {
+                // still dump the screen, it usually shows how far the rom got
+                eprintln!("The rom crashed on frame {}: {err}", headless.frame);
+                status = 1;
+                break;
+            }
+        }
+    }
+
+    match output {
+        Some(path) if path.ends_with(".pbm") => {
+            fs::write(&path, headless.to_pbm()).expect("Couldn't write the screen dump.")
+        }
+        Some(path) => {
+            fs::write(&path, headless.to_ascii()).expect("Couldn't write the screen dump.")
+        }
+        None => print!("{}", headless.to_ascii()),
+    }
+    process::exit(status);
+}
@@ -0,0 +1,376 @@
+// plays a rom in a terminal, for machines where SDL can't open a window (over ssh, say):
+//
+//   rust-8-term <rom> [--braille] [--palette P] [--platform P] [--quirks Q]
+//
+// the screen is drawn with half blocks, two pixels per character, or with braille dots,
+// eight pixels per character, in the rom's colors. only the characters that changed since
+// the last frame are redrawn. terminals only tell us when a key goes down (and then repeat
+// it), so a keypad key is let go once its host key hasn't come in for --key-timeout ms.
+// Esc or Ctrl-C quits.
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Color, Colors, Print, ResetColor, SetColors};
+use crossterm::{cursor, execute, queue, terminal};
+use rust_8::database::RomDatabase;
+use rust_8::frontend::{NoAudio, SystemClock, DEFAULT_TICKRATE};
+use rust_8::palette::Palette;
+use rust_8::rng::{Generator, VipRandom};
+use rust_8::{Chip8, Command, DisplaySink, InputSource, Platform, Quirks, Rom, Runner};
+use std::env;
+use std::io::{self, Stdout, Write};
+use std::path::Path;
+use std::process;
+use std::time::{Duration, Instant};
+
+const DEFAULT_KEY_TIMEOUT: Duration = Duration::from_millis(150);
+
+// the same layout as the window: 1234/QWER/ASDF/ZXCV, and the arrows and space for 2/4/6/8/5
+const KEYMAP: [(char, usize); 16] = [
+    ('x', 0x0),
+    ('1', 0x1),
+    ('2', 0x2),
+    ('3', 0x3),
+    ('q', 0x4),
+    ('w', 0x5),
+    ('e', 0x6),
+    ('a', 0x7),
+    ('s', 0x8),
+    ('d', 0x9),
+    ('z', 0xA),
+    ('c', 0xB),
+    ('4', 0xC),
+    ('r', 0xD),
+    ('f', 0xE),
+    ('v', 0xF),
+];
+
+fn keypad_key(code: KeyCode) -> Option<usize> {
+    match code {
+        KeyCode::Up => Some(0x2),
+        KeyCode::Left => Some(0x4),
+        KeyCode::Right => Some(0x6),
+        KeyCode::Down => Some(0x8),
+        KeyCode::Char(' ') => Some(0x5),
+        KeyCode::Char(c) => KEYMAP
+            .iter()
+            .find(|(key, _)| *key == c.to_ascii_lowercase())
+            .map(|(_, keypad_key)| *keypad_key),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Style {
+    // 1x2 pixels per character, each half in its own color
+    HalfBlock,
+    // 2x4 pixels per character, lit dots in one color
+    Braille,
+}
+
+impl Style {
+    fn cell_size(&self) -> (usize, usize) {
+        match self {
+            Self::HalfBlock => (1, 2),
+            Self::Braille => (2, 4),
+        }
+    }
+}
+
+// a character on screen, its colors are palette indices
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cell {
+    symbol: char,
+    foreground: u8,
+    background: u8,
+}
+
+impl Cell {
+    // `pixels` are the cell's pixels row by row, off the screen's edge counts as unlit
+    fn new(style: Style, pixels: &[u8]) -> Self {
+        match style {
+            Style::HalfBlock => Self {
+                symbol: '▀',
+                foreground: pixels[0],
+                background: pixels[1],
+            },
+            Style::Braille => {
+                // the bit of each dot, row by row, as unicode numbers them
+                const DOTS: [u32; 8] = [0x01, 0x08, 0x02, 0x10, 0x04, 0x20, 0x40, 0x80];
+                let mut dots = 0;
+                for (pixel, dot) in pixels.iter().zip(DOTS) {
+                    if *pixel != 0 {
+                        dots |= dot;
+                    }
+                }
+                Self {
+                    symbol: char::from_u32(0x2800 + dots).unwrap_or(' '),
+                    // the cell can only have one color, the highest plane wins
+                    foreground: pixels.iter().copied().max().unwrap_or(0),
+                    background: 0,
+                }
+            }
+        }
+    }
+}
+
+struct Terminal {
+    stdout: Stdout,
+    style: Style,
+    palette: [Color; 4],
+    key_timeout: Duration,
+    // when each keypad key was last pressed, it's let go once that's too long ago
+    pressed_at: [Option<Instant>; 16],
+    // what's on the terminal right now, columns x rows
+    cells: Vec<Cell>,
+    columns: usize,
+    rows: usize,
+}
+
+impl Terminal {
+    fn new(style: Style, palette: Palette, key_timeout: Duration) -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let mut stdout = io::stdout();
+        execute!(
+            stdout,
+            terminal::EnterAlternateScreen,
+            cursor::Hide,
+            terminal::Clear(terminal::ClearType::All)
+        )?;
+        Ok(Self {
+            stdout,
+            style,
+            palette: palette.0.map(|rgb| Color::Rgb {
+                r: rgb.r,
+                g: rgb.g,
+                b: rgb.b,
+            }),
+            key_timeout,
+            pressed_at: [None; 16],
+            cells: Vec::new(),
+            columns: 0,
+            rows: 0,
+        })
+    }
+
+    // the next draw repaints everything
+    fn invalidate(&mut self) {
+        self.cells.clear();
+        let _ = queue!(
+            self.stdout,
+            ResetColor,
+            terminal::Clear(terminal::ClearType::All)
+        );
+    }
+
+    fn render(&mut self, buffer: &[u8], width: usize, height: usize) -> io::Result<()> {
+        let (cell_width, cell_height) = self.style.cell_size();
+        let columns = width.div_ceil(cell_width);
+        let rows = height.div_ceil(cell_height);
+        if (columns, rows) != (self.columns, self.rows) {
+            self.invalidate();
+            (self.columns, self.rows) = (columns, rows);
+        }
+
+        let mut pixels = Vec::with_capacity(cell_width * cell_height);
+        let mut cells = Vec::with_capacity(columns * rows);
+        for row in 0..rows {
+            for column in 0..columns {
+                pixels.clear();
+                for y in row * cell_height..(row + 1) * cell_height {
+                    for x in column * cell_width..(column + 1) * cell_width {
+                        let inside = x < width && y < height;
+                        pixels.push(if inside {
+                            buffer[y * width + x] & 0x3
+                        } else {
+                            0
+                        });
+                    }
+                }
+                cells.push(Cell::new(self.style, &pixels));
+            }
+        }
+
+        for (i, cell) in cells.iter().enumerate() {
+            if self.cells.get(i) == Some(cell) {
+                continue;
+            }
+            let (column, row) = ((i % columns) as u16, (i / columns) as u16);
+            queue!(
+                self.stdout,
+                cursor::MoveTo(column, row),
+                SetColors(Colors::new(
+                    self.palette[cell.foreground as usize],
+                    self.palette[cell.background as usize]
+                )),
+                Print(cell.symbol)
+            )?;
+        }
+        self.cells = cells;
+        self.stdout.flush()
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = execute!(
+            self.stdout,
+            ResetColor,
+            cursor::Show,
+            terminal::LeaveAlternateScreen
+        );
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+impl DisplaySink for Terminal {
+    fn draw(&mut self, buffer: &[u8], width: usize, height: usize) {
+        // a terminal that went away takes the screen with it, there's nowhere to report to
+        let _ = self.render(buffer, width, height);
+    }
+}
+
+impl InputSource for Terminal {
+    fn process_input(&mut self, keypad: &mut [bool; 16]) -> Vec<Command> {
+        while event::poll(Duration::ZERO).unwrap_or(false) {
+            let Ok(event) = event::read() else {
+                break;
+            };
+            match event {
+                Event::Key(KeyEvent {
+                    code: KeyCode::Esc, ..
+                }) => return vec![Command::Quit],
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('c'),
+                    modifiers,
+                    ..
+                }) if modifiers.contains(KeyModifiers::CONTROL) => return vec![Command::Quit],
+                // only a few terminals report releases, the timeout covers the rest
+                Event::Key(KeyEvent { code, kind, .. }) => {
+                    if let Some(key) = keypad_key(code) {
+                        self.pressed_at[key] = match kind {
+                            KeyEventKind::Release => None,
+                            _ => Some(Instant::now()),
+                        };
+                    }
+                }
+                Event::Resize(..) => self.invalidate(),
+                _ => {}
+            }
+        }
+
+        for (key, pressed_at) in self.pressed_at.iter_mut().enumerate() {
+            if pressed_at.is_some_and(|at| at.elapsed() > self.key_timeout) {
+                *pressed_at = None;
+            }
+            keypad[key] = pressed_at.is_some();
+        }
+        Vec::new()
+    }
+}
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: rust-8-term <rom> [--braille] [--palette P] [--platform P] [--quirks Q] \
+         [--tickrate N] [--key-timeout MS] [--seed N] [--rng vip|xorshift] [--vip-rom FILE] [--rom-db DIR]"
+    );
+    process::exit(2);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut rom_path = None;
+    let mut style = Style::HalfBlock;
+    let mut palette = None;
+    let mut platform = None;
+    let mut quirks = None;
+    let mut seed = None;
+    let mut generator = None;
+    let mut vip_rom = None;
+    let mut tickrate = None;
+    let mut rom_db = None;
+    let mut key_timeout = DEFAULT_KEY_TIMEOUT;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--braille" => style = Style::Braille,
+            "--palette" => palette = Some(Palette::from_name(value()).unwrap_or_else(|| usage())),
+            "--platform" => {
+                platform = Some(Platform::from_name(value()).unwrap_or_else(|| usage()))
+            }
+            "--quirks" => quirks = Some(Quirks::from_name(value()).unwrap_or_else(|| usage())),
+            "--seed" => seed = Some(value().parse().unwrap_or_else(|_| usage())),
+            "--rng" => generator = Some(Generator::from_name(value()).unwrap_or_else(|| usage())),
+            "--vip-rom" => vip_rom = Some(value().clone()),
+            "--rom-db" => rom_db = Some(value().clone()),
+            "--tickrate" => tickrate = Some(value().parse().unwrap_or_else(|_| usage())),
+            "--key-timeout" => {
+                let millis = value().parse().unwrap_or_else(|_| usage());
+                key_timeout = Duration::from_millis(millis);
+            }
+            _ if rom_path.is_none() && !arg.starts_with("--") => rom_path = Some(arg.clone()),
+            _ => usage(),
+        }
+    }
+    let rom_path = rom_path.unwrap_or_else(|| usage());
+    // a dump of the interpreter is only any use to the VIP's generator
+    if vip_rom.is_some() && generator == Some(Generator::Xorshift) {
+        usage();
+    }
+    let rom = Rom::read(&rom_path).unwrap_or_else(|err| {
+        eprintln!("Couldn't load {rom_path}: {err}");
+        process::exit(1);
+    });
+
+    // the command line, then the rom's own options, then the rom database's
+    let database = match rom_db {
+        Some(folder) => RomDatabase::load(Path::new(&folder)).unwrap_or_else(|err| {
+            eprintln!("Couldn't load the rom database in {folder}: {err}");
+            process::exit(1);
+        }),
+        None => RomDatabase::bundled(),
+    };
+    let options = match database.lookup(&rom.program) {
+        Some(entry) => rom.options.or(entry.options),
+        None => rom.options,
+    };
+    let (platform, quirks) = options.machine(platform, quirks);
+    let tickrate = tickrate.or(options.tickrate).unwrap_or(DEFAULT_TICKRATE);
+    let palette = palette.or(options.palette).unwrap_or_default();
+
+    let mut chip8 = Chip8::new(platform, quirks);
+    if let Some(path) = vip_rom {
+        let rng = VipRandom::load(rand::random(), Path::new(&path)).unwrap_or_else(|err| {
+            eprintln!("Couldn't load the VIP interpreter in {path}: {err}");
+            process::exit(1);
+        });
+        chip8.set_random_source(Box::new(rng));
+    } else if let Some(generator) = generator {
+        chip8.set_random_source(generator.source(rand::random()));
+    }
+    if let Some(seed) = seed {
+        chip8.seed_random(seed);
+    }
+    if let Err(err) = chip8.load_program(&rom.program) {
+        eprintln!("Couldn't load {rom_path}: {err}");
+        process::exit(1);
+    }
+
+    let mut terminal = Terminal::new(style, palette, key_timeout).unwrap_or_else(|err| {
+        eprintln!("Couldn't set up the terminal: {err}");
+        process::exit(1);
+    });
+    let result = Runner::new(tickrate).run(
+        &mut chip8,
+        &mut terminal,
+        &mut NoAudio,
+        &mut SystemClock::new(),
+    );
+    // put the terminal back before saying anything
+    drop(terminal);
+    if let Err(err) = result {
+        eprintln!("The rom crashed: {err}");
+        process::exit(1);
+    }
+}
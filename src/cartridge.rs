@@ -0,0 +1,134 @@
+// Octo shares programs as "cartridges": a gif whose pixels also carry the program source
+// and the options it was written with. every pixel holds 2 bits in the low bits of its
+// palette index, 4 pixels make a byte (high bits first), and the frames follow each other.
+// the bytes are a big endian u32 length, then that many bytes of json:
+//
+//   {"program": "<octo source>", "options": {"tickrate": 20, "shiftQuirks": false, ...}}
+
+use crate::assembler::assemble;
+use crate::palette::{Palette, Rgb};
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+use crate::rom::{Rom, RomOptions};
+use serde::Deserialize;
+use std::io;
+
+const GIF_MAGIC: [&[u8]; 2] = [b"GIF87a", b"GIF89a"];
+
+// octo's program size settings. it offers 3216 for the VIP and 3583 for SUPER-CHIP, while
+// its own 3584 and XO-CHIP's 65024 both allow every instruction.
+const VIP_MAX_SIZE: u32 = 3216;
+const SCHIP_MAX_SIZE: u32 = 3583;
+
+#[derive(Deserialize)]
+struct Cartridge {
+    program: String,
+    #[serde(default)]
+    options: Options,
+}
+
+// the options octo saves with a program. anything missing falls back to octo's defaults.
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct Options {
+    tickrate: Option<u32>,
+    max_size: Option<u32>,
+    fill_color: Option<String>,
+    fill_color2: Option<String>,
+    blend_color: Option<String>,
+    background_color: Option<String>,
+    shift_quirks: bool,
+    load_store_quirks: bool,
+    jump_quirks: bool,
+    logic_quirks: bool,
+    clip_quirks: bool,
+    v_blank_quirks: bool,
+}
+
+impl Options {
+    fn into_rom_options(self) -> RomOptions {
+        let platform = self.max_size.map(|max_size| match max_size {
+            size if size > SCHIP_MAX_SIZE => Platform::XoChip,
+            size if size > VIP_MAX_SIZE => Platform::SuperChip,
+            _ => Platform::Chip8,
+        });
+
+        let defaults = Palette::octo().0;
+        let color = |hex: &Option<String>, default: Rgb| {
+            hex.as_deref().and_then(Rgb::from_hex).unwrap_or(default)
+        };
+        let palette = Palette([
+            color(&self.background_color, defaults[0]),
+            color(&self.fill_color, defaults[1]),
+            color(&self.fill_color2, defaults[2]),
+            color(&self.blend_color, defaults[3]),
+        ]);
+
+        RomOptions {
+            platform,
+            tickrate: self.tickrate,
+            quirks: Some(Quirks {
+                shift: self.shift_quirks,
+                load_store: self.load_store_quirks,
+                jump: self.jump_quirks,
+                vf_reset: self.logic_quirks,
+                clip: self.clip_quirks,
+                display_wait: self.v_blank_quirks,
+            }),
+            palette: Some(palette),
+            ..RomOptions::default()
+        }
+    }
+}
+
+pub fn is_cartridge(bytes: &[u8]) -> bool {
+    GIF_MAGIC.iter().any(|magic| bytes.starts_with(magic))
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+pub fn decode(bytes: &[u8]) -> io::Result<Rom> {
+    let payload = payload(bytes)?;
+    let length = payload
+        .first_chunk::<4>()
+        .map(|length| u32::from_be_bytes(*length) as usize)
+        .ok_or_else(|| invalid("the gif doesn't hold a cartridge"))?;
+    let json = payload
+        .get(4..length.saturating_add(4))
+        .ok_or_else(|| invalid("the cartridge data is cut short"))?;
+
+    let cartridge: Cartridge = serde_json::from_slice(json)
+        .map_err(|err| invalid(format!("the cartridge data isn't valid: {err}")))?;
+    let program = assemble(&cartridge.program)
+        .map_err(|err| invalid(format!("the cartridge program doesn't assemble: {err}")))?;
+
+    Ok(Rom {
+        program,
+        options: cartridge.options.into_rom_options(),
+    })
+}
+
+// the bytes spread over the pixels of every frame
+fn payload(bytes: &[u8]) -> io::Result<Vec<u8>> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = options
+        .read_info(bytes)
+        .map_err(|err| invalid(format!("can't read the gif: {err}")))?;
+
+    let mut payload = Vec::new();
+    while let Some(frame) = decoder
+        .read_next_frame()
+        .map_err(|err| invalid(format!("can't read the gif: {err}")))?
+    {
+        for pixels in frame.buffer.chunks_exact(4) {
+            let byte = pixels
+                .iter()
+                .fold(0, |byte, pixel| byte << 2 | (pixel & 0x3));
+            payload.push(byte);
+        }
+    }
+    Ok(payload)
+}
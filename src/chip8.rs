@@ -1,6 +1,10 @@
-use rand::prelude::*;
-use std::fs::File;
-use std::io::Read;
+use crate::error::{Chip8Error, StateError};
+use crate::instruction::{decode, Instruction};
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+use crate::rng::{RandomSource, Xorshift};
+use crate::rom::{Rom, RomOptions};
+use crate::state::{StateReader, StateWriter};
 
 const FONT: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
@@ -21,59 +25,207 @@ const FONT: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+// the 8x10 digits SUPER-CHIP uses in high resolution mode
+const BIG_FONT: [u8; 160] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x18, 0x3C, 0x66, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFE, 0xC3, 0xC3, 0xFE, 0xFE, 0xC3, 0xC3, 0xFE, 0xFC, // B
+    0x3C, 0x7E, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0x7E, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
 const VIDEO_WIDTH: usize = 64;
 const VIDEO_HEIGHT: usize = 32;
+const HIRES_VIDEO_WIDTH: usize = 128;
+const HIRES_VIDEO_HEIGHT: usize = 64;
 const FONT_ADDRESS: usize = 0x050;
+// where roms are loaded and execution starts
+pub const PROGRAM_START: usize = 0x200;
+const BIG_FONT_ADDRESS: usize = FONT_ADDRESS + FONT.len();
+
+// XO-CHIP's F000 NNNN is the only instruction that is two words long
+const LONG_LOAD_OPCODE: u16 = 0xF000;
 
 pub struct Chip8 {
-    memory: Box<[u8; 4096]>,
+    memory: Box<[u8]>,
     pc: u16,
     stack: [u16; 16],
     sp: u16,
     index: u16,
     delay_timer: u8,
     sound_timer: u8,
-    video_buffer: [u32; 64 * 32],
+    // every pixel holds one bit per bitplane, so plain chip-8 only ever uses 0 and 1
+    video_buffer: Vec<u8>,
+    hires: bool,
+    plane_mask: u8,
     registers: [u8; 16],
     keypad: [bool; 16],
     opcode: u16,
-    rng: ThreadRng,
+    rng: Box<dyn RandomSource>,
+    platform: Platform,
+    quirks: Quirks,
+    vblank: bool,
+    // a DXYN is stalled until the next tick_clock, which clears it, so it's never saved
+    waiting_for_vblank: bool,
+    rpl_flags: [u8; 16],
+    exited: bool,
+    audio_pattern: Option<[u8; 16]>,
+    pitch: u8,
 }
 
 impl Chip8 {
-    pub fn new() -> Self {
-        // hardcoding the font into memory
-        let mut memory = [0u8; 4096];
-        let font_start = 0x050;
-        for (i, byte) in FONT.iter().enumerate() {
-            memory[font_start + i] = *byte;
-        }
+    pub fn new(platform: Platform, quirks: Quirks) -> Self {
+        // hardcoding the fonts into memory
+        let mut memory = vec![0u8; platform.memory_size()];
+        memory[FONT_ADDRESS..FONT_ADDRESS + FONT.len()].copy_from_slice(&FONT);
+        memory[BIG_FONT_ADDRESS..BIG_FONT_ADDRESS + BIG_FONT.len()].copy_from_slice(&BIG_FONT);
         // TODO: i'm putting all zeroes for now, need to check actual init values
         Self {
-            memory: Box::new(memory),
-            pc: 0x200,
+            memory: memory.into_boxed_slice(),
+            pc: PROGRAM_START as u16,
             stack: [0u16; 16],
             sp: 0,
             index: 0,
             delay_timer: 0,
             sound_timer: 0,
-            video_buffer: [0; VIDEO_WIDTH * VIDEO_HEIGHT],
+            video_buffer: vec![0; VIDEO_WIDTH * VIDEO_HEIGHT],
+            hires: false,
+            plane_mask: 1,
             registers: [0; 16],
             keypad: [false; 16],
             opcode: 0,
-            rng: rand::rng(),
+            rng: Box::new(Xorshift::new(rand::random())),
+            platform,
+            quirks,
+            vblank: false,
+            waiting_for_vblank: false,
+            rpl_flags: [0; 16],
+            exited: false,
+            audio_pattern: None,
+            pitch: 64,
         }
     }
 
-    pub fn get_video_buffer(&self) -> &[u32; 64 * 32] {
+    pub fn get_video_buffer(&self) -> &[u8] {
         &self.video_buffer
     }
 
+    // (width, height) of the video buffer in the current display mode
+    pub fn get_resolution(&self) -> (usize, usize) {
+        if self.hires {
+            (HIRES_VIDEO_WIDTH, HIRES_VIDEO_HEIGHT)
+        } else {
+            (VIDEO_WIDTH, VIDEO_HEIGHT)
+        }
+    }
+
+    // set once a SUPER-CHIP rom runs 00FD
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    pub fn get_sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    // the 128 bit sample loaded by XO-CHIP's F002, if the rom ever loaded one
+    pub fn get_audio_pattern(&self) -> Option<&[u8; 16]> {
+        self.audio_pattern.as_ref()
+    }
+
+    // playback rate of the audio pattern in bits per second
+    pub fn get_audio_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+
+    // read only views of the machine, for debuggers and other tools
+
+    pub fn get_pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn get_index(&self) -> u16 {
+        self.index
+    }
+
+    pub fn get_registers(&self) -> &[u8; 16] {
+        &self.registers
+    }
+
+    pub fn get_registers_mut(&mut self) -> &mut [u8; 16] {
+        &mut self.registers
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    pub fn set_index(&mut self, index: u16) {
+        self.index = index;
+    }
+
+    // only the addresses currently on the stack, oldest first
+    pub fn get_stack(&self) -> &[u16] {
+        &self.stack[..self.sp as usize]
+    }
+
+    pub fn get_sp(&self) -> u16 {
+        self.sp
+    }
+
+    pub fn set_sp(&mut self, sp: u16) {
+        self.sp = sp.min(self.stack.len() as u16);
+    }
+
+    pub fn get_delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn get_memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn get_memory_mut(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
+
+    pub fn get_platform(&self) -> Platform {
+        self.platform
+    }
+
+    // the same seed gives the same CXNN results, so runs with the same input play out the same
+    pub fn seed_random(&mut self, seed: u64) {
+        self.rng.set_state(seed);
+    }
+
+    // save states keep its state but not which source it is, that's up to whoever loads them
+    pub fn set_random_source(&mut self, source: Box<dyn RandomSource>) {
+        self.rng = source;
+    }
+
     pub fn get_keypad(&mut self) -> &mut [bool; 16] {
         &mut self.keypad
     }
 
+    // true while DXYN waits for the vertical blank, nothing runs until the next tick_clock
+    pub fn is_waiting_for_vblank(&self) -> bool {
+        self.waiting_for_vblank
+    }
+
     pub fn tick_clock(&mut self) {
+        self.vblank = true;
+        self.waiting_for_vblank = false;
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
         }
@@ -82,13 +234,17 @@ impl Chip8 {
         }
     }
 
-    pub fn load_rom(&mut self, file_name: &String) -> std::io::Result<()> {
-        let mut file = File::open(file_name)?;
-        let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer)?;
+    // loads a rom file, unpacking Octo cartridges. the options that came with it are
+    // returned for the caller to apply, the interpreter only takes the program.
+    pub fn load_rom(&mut self, file_name: &str) -> std::io::Result<RomOptions> {
+        let rom = Rom::read(file_name)?;
+        self.load_program(&rom.program)?;
+        Ok(rom.options)
+    }
 
-        let start = 0x200;
-        let end = start + buffer.len();
+    pub fn load_program(&mut self, program: &[u8]) -> std::io::Result<()> {
+        let start = PROGRAM_START;
+        let end = start + program.len();
         if end > self.memory.len() {
             return Err(std::io::Error::new(
                 std::io::ErrorKind::OutOfMemory,
@@ -96,188 +252,385 @@ impl Chip8 {
             ));
         }
 
-        self.memory[start..end].copy_from_slice(&buffer);
+        self.memory[start..end].copy_from_slice(program);
+
+        Ok(())
+    }
+
+    // the complete machine, including the platform and quirks it runs with
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        state.u8(match self.platform {
+            Platform::Chip8 => 0,
+            Platform::SuperChip => 1,
+            Platform::XoChip => 2,
+        });
+        let quirks = [
+            self.quirks.shift,
+            self.quirks.load_store,
+            self.quirks.jump,
+            self.quirks.vf_reset,
+            self.quirks.clip,
+            self.quirks.display_wait,
+        ];
+        state.u8(quirks
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (i, quirk)| bits | (*quirk as u8) << i));
+
+        state.bytes(&self.memory);
+        state.u16(self.pc);
+        for address in self.stack {
+            state.u16(address);
+        }
+        state.u16(self.sp);
+        state.u16(self.index);
+        state.u8(self.delay_timer);
+        state.u8(self.sound_timer);
+        state.bytes(&self.video_buffer);
+        state.bool(self.hires);
+        state.u8(self.plane_mask);
+        state.bytes(&self.registers);
+        for key in self.keypad {
+            state.bool(key);
+        }
+        state.u16(self.opcode);
+        state.u64(self.rng.state());
+        state.bool(self.vblank);
+        state.bytes(&self.rpl_flags);
+        state.bool(self.exited);
+        state.bool(self.audio_pattern.is_some());
+        state.bytes(&self.audio_pattern.unwrap_or_default());
+        state.u8(self.pitch);
+        state.finish()
+    }
+
+    // restores a state from save_state. on error the machine is left untouched
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let mut state = StateReader::open(state)?;
+        let platform = match state.u8()? {
+            0 => Platform::Chip8,
+            1 => Platform::SuperChip,
+            2 => Platform::XoChip,
+            _ => return Err(StateError::InvalidField { field: "platform" }),
+        };
+        let quirk_bits = state.u8()?;
+        let quirk = |i: u8| quirk_bits & (1 << i) != 0;
+        let quirks = Quirks {
+            shift: quirk(0),
+            load_store: quirk(1),
+            jump: quirk(2),
+            vf_reset: quirk(3),
+            clip: quirk(4),
+            display_wait: quirk(5),
+        };
+
+        let memory = state.bytes()?;
+        if memory.len() != platform.memory_size() {
+            return Err(StateError::InvalidField { field: "memory" });
+        }
+        let pc = state.u16()?;
+        let mut stack = [0u16; 16];
+        for address in stack.iter_mut() {
+            *address = state.u16()?;
+        }
+        let sp = state.u16()?;
+        if sp as usize > stack.len() {
+            return Err(StateError::InvalidField {
+                field: "stack pointer",
+            });
+        }
+        let index = state.u16()?;
+        let delay_timer = state.u8()?;
+        let sound_timer = state.u8()?;
+        let video_buffer = state.bytes()?;
+        let hires = state.bool()?;
+        let (width, height) = if hires {
+            (HIRES_VIDEO_WIDTH, HIRES_VIDEO_HEIGHT)
+        } else {
+            (VIDEO_WIDTH, VIDEO_HEIGHT)
+        };
+        if video_buffer.len() != width * height {
+            return Err(StateError::InvalidField {
+                field: "video buffer",
+            });
+        }
+        let plane_mask = state.u8()?;
+        let registers = state.array::<16>("registers")?;
+        let mut keypad = [false; 16];
+        for key in keypad.iter_mut() {
+            *key = state.bool()?;
+        }
+        let opcode = state.u16()?;
+        let rng_state = state.u64()?;
+        let vblank = state.bool()?;
+        let rpl_flags = state.array::<16>("rpl flags")?;
+        let exited = state.bool()?;
+        let has_audio_pattern = state.bool()?;
+        let audio_pattern = state.array::<16>("audio pattern")?;
+        let pitch = state.u8()?;
 
+        // only touch the machine once the whole state parsed
+        self.platform = platform;
+        self.quirks = quirks;
+        self.memory = memory.into();
+        self.pc = pc;
+        self.stack = stack;
+        self.sp = sp;
+        self.index = index;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.video_buffer = video_buffer.to_vec();
+        self.hires = hires;
+        self.plane_mask = plane_mask;
+        self.registers = registers;
+        self.keypad = keypad;
+        self.opcode = opcode;
+        self.rng.set_state(rng_state);
+        self.vblank = vblank;
+        self.rpl_flags = rpl_flags;
+        self.exited = exited;
+        self.audio_pattern = has_audio_pattern.then_some(audio_pattern);
+        self.pitch = pitch;
+        // a DXYN waiting on the old machine's vblank has nothing to do with this one
+        self.waiting_for_vblank = false;
         Ok(())
     }
 
-    pub fn tick(&mut self) {
+    pub fn tick(&mut self) -> Result<(), Chip8Error> {
+        if self.exited || self.waiting_for_vblank {
+            return Ok(());
+        }
+
         // fetch
-        let high_byte = self.memory[self.pc as usize] as u16;
-        let low_byte = self.memory[(self.pc + 1) as usize] as u16;
-        self.opcode = high_byte << 8 | low_byte;
+        self.opcode = self.read_word(self.pc as usize).map_err(|err| match err {
+            // nothing has run yet, so the fault belongs to the fetch itself
+            Chip8Error::MemoryOutOfBounds { addr, .. } => {
+                Chip8Error::MemoryOutOfBounds { addr, pc: self.pc }
+            }
+            err => err,
+        })?;
 
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
 
         // decode and execute
+        self.execute(decode(self.opcode))
+    }
 
-        // we start by capturing and storing the nibbles (4 bit)
-        let first_nibble: u8 = (self.opcode >> 12) as u8; // the first nibble
-        let x: u8 = ((self.opcode >> 8) & 0x000Fu16) as u8; // the second nibble
-        let y: u8 = ((self.opcode >> 4) & 0x000Fu16) as u8; // the third nibble
-        let n: u8 = (self.opcode & 0x000Fu16) as u8; // the fourth nibble
-        let nn: u8 = (self.opcode & 0x00FFu16) as u8; // second byte
-        let nnn: u16 = (self.opcode & 0x0FFFu16) as u16; // second, third, fourth nibbles
-
-        match first_nibble {
-            0x0 => match nn {
-                0xE0 => {
-                    self.clear_screen();
-                }
-                0xEE => {
-                    self.ret();
-                }
-                _ => {
-                    // ignore the rest
-                }
-            },
-            0x1 => {
-                self.jump(nnn);
-            }
-            0x2 => {
-                self.call(nnn);
-            }
-            0x3 => {
-                self.skip_if_equals_byte(x, nn);
-            }
-            0x4 => {
-                self.skip_if_not_equals_byte(x, nn);
-            }
-            0x5 => {
-                self.skip_if_equals_registers(x, y);
-            }
-            0x6 => {
-                self.load(x, nn);
-            }
-            0x7 => {
-                self.add_to_register(x, nn);
-            }
-            0x8 => match n {
-                0x0 => {
-                    self.load_register(x, y);
-                }
-                0x1 => {
-                    self.or_registers(x, y);
-                }
-                0x2 => {
-                    self.and_registers(x, y);
-                }
-                0x3 => {
-                    self.xor_registers(x, y);
-                }
-                0x4 => {
-                    self.add_registers(x, y);
-                }
-                0x5 => {
-                    self.sub_registers(x, y);
-                }
-                0x6 => {
-                    self.shr_register(x);
-                }
-                0x7 => {
-                    self.subn_registers(x, y);
-                }
-                0xE => {
-                    self.shl_register(x);
-                }
-                _ => {}
-            },
-            0x9 => {
-                self.sne(x, y);
-            }
-            0xA => {
-                self.load_address_to_index(nnn);
-            }
-            0xB => {
-                self.jump_v0(nnn);
-            }
-            0xC => {
-                self.rnd_and_byte(x, nn);
-            }
-            0xD => {
-                self.draw(x, y, n);
-            }
-            0xE => match nn {
-                0x9E => {
-                    self.skip_key(x);
-                }
-                0xA1 => {
-                    self.skip_not_key(x);
-                }
-                _ => {}
-            },
-            0xF => match nn {
-                0x07 => {
-                    self.load_timer(x);
-                }
-                0x0A => {
-                    self.load_key(x);
-                }
-                0x15 => {
-                    self.set_delay(x);
-                }
-                0x18 => {
-                    self.set_sound(x);
-                }
-                0x1E => {
-                    self.add_to_index(x);
-                }
-                0x29 => {
-                    self.ld_digit_to_index(x);
-                }
-                0x33 => {
-                    self.bcd_to_index(x);
-                }
-                0x55 => {
-                    self.store_to_index(x);
-                }
-                0x65 => {
-                    self.read_from_index(x);
-                }
-                _ => {}
-            },
+    pub fn execute(&mut self, instruction: Instruction) -> Result<(), Chip8Error> {
+        if !instruction.is_supported_on(self.platform) {
+            return Err(match instruction {
+                Instruction::Unknown { opcode } => Chip8Error::UnknownOpcode {
+                    opcode,
+                    pc: self.fault_pc(),
+                },
+                _ => self.unknown_opcode(),
+            });
+        }
 
-            _ => {}
+        match instruction {
+            Instruction::ClearScreen => self.clear_screen(),
+            Instruction::Return => self.ret()?,
+            Instruction::ScrollDown { n } => self.scroll(0, n as isize),
+            Instruction::ScrollUp { n } => self.scroll(0, -(n as isize)),
+            Instruction::ScrollRight => self.scroll(4, 0),
+            Instruction::ScrollLeft => self.scroll(-4, 0),
+            Instruction::Exit => self.exit(),
+            Instruction::LowRes => self.set_hires(false),
+            Instruction::HighRes => self.set_hires(true),
+            Instruction::Jump { nnn } => self.jump(nnn),
+            Instruction::Call { nnn } => self.call(nnn)?,
+            Instruction::SkipIfEqualsByte { x, nn } => self.skip_if_equals_byte(x, nn)?,
+            Instruction::SkipIfNotEqualsByte { x, nn } => self.skip_if_not_equals_byte(x, nn)?,
+            Instruction::SkipIfEqualsRegisters { x, y } => self.skip_if_equals_registers(x, y)?,
+            Instruction::StoreRange { x, y } => self.store_range(x, y)?,
+            Instruction::ReadRange { x, y } => self.read_range(x, y)?,
+            Instruction::Load { x, nn } => self.load(x, nn),
+            Instruction::AddToRegister { x, nn } => self.add_to_register(x, nn),
+            Instruction::LoadRegister { x, y } => self.load_register(x, y),
+            Instruction::Or { x, y } => self.or_registers(x, y),
+            Instruction::And { x, y } => self.and_registers(x, y),
+            Instruction::Xor { x, y } => self.xor_registers(x, y),
+            Instruction::Add { x, y } => self.add_registers(x, y),
+            Instruction::Sub { x, y } => self.sub_registers(x, y),
+            Instruction::ShiftRight { x, y } => self.shr_register(x, y),
+            Instruction::SubN { x, y } => self.subn_registers(x, y),
+            Instruction::ShiftLeft { x, y } => self.shl_register(x, y),
+            Instruction::SkipIfNotEqualsRegisters { x, y } => self.sne(x, y)?,
+            Instruction::LoadIndex { nnn } => self.load_address_to_index(nnn),
+            Instruction::JumpV0 { nnn } => self.jump_v0(nnn),
+            Instruction::Random { x, nn } => self.rnd_and_byte(x, nn),
+            Instruction::Draw { x, y, n } => self.draw(x, y, n)?,
+            Instruction::SkipKey { x } => self.skip_key(x)?,
+            Instruction::SkipNotKey { x } => self.skip_not_key(x)?,
+            Instruction::LoadLongIndex => self.load_long_address_to_index()?,
+            Instruction::SelectPlanes { x } => self.select_planes(x),
+            Instruction::LoadAudioPattern => self.load_audio_pattern()?,
+            Instruction::LoadDelay { x } => self.load_timer(x),
+            Instruction::WaitKey { x } => self.load_key(x),
+            Instruction::SetDelay { x } => self.set_delay(x),
+            Instruction::SetSound { x } => self.set_sound(x),
+            Instruction::AddToIndex { x } => self.add_to_index(x),
+            Instruction::LoadDigit { x } => self.ld_digit_to_index(x),
+            Instruction::LoadBigDigit { x } => self.ld_big_digit_to_index(x),
+            Instruction::Bcd { x } => self.bcd_to_index(x)?,
+            Instruction::SetPitch { x } => self.set_pitch(x),
+            Instruction::StoreRegisters { x } => self.store_to_index(x)?,
+            Instruction::ReadRegisters { x } => self.read_from_index(x)?,
+            Instruction::StoreFlags { x } => self.store_rpl_flags(x),
+            Instruction::ReadFlags { x } => self.read_rpl_flags(x),
+            Instruction::Unknown { .. } => unreachable!("unknown opcodes are never supported"),
         }
+
+        Ok(())
+    }
+
+    // error helpers. by the time an instruction runs the pc already points past it
+
+    fn fault_pc(&self) -> u16 {
+        self.pc.wrapping_sub(2)
+    }
+
+    fn unknown_opcode(&self) -> Chip8Error {
+        Chip8Error::UnknownOpcode {
+            opcode: self.opcode,
+            pc: self.fault_pc(),
+        }
+    }
+
+    fn read_byte(&self, address: usize) -> Result<u8, Chip8Error> {
+        self.memory
+            .get(address)
+            .copied()
+            .ok_or(Chip8Error::MemoryOutOfBounds {
+                addr: address,
+                pc: self.fault_pc(),
+            })
+    }
+
+    fn write_byte(&mut self, address: usize, value: u8) -> Result<(), Chip8Error> {
+        let pc = self.fault_pc();
+        let byte = self
+            .memory
+            .get_mut(address)
+            .ok_or(Chip8Error::MemoryOutOfBounds { addr: address, pc })?;
+        *byte = value;
+        Ok(())
+    }
+
+    fn read_word(&self, address: usize) -> Result<u16, Chip8Error> {
+        let high_byte = self.read_byte(address)? as u16;
+        let low_byte = self.read_byte(address + 1)? as u16;
+        Ok(high_byte << 8 | low_byte)
+    }
+
+    fn key_pressed(&self, v_x: u8) -> Result<bool, Chip8Error> {
+        let key = self.registers[v_x as usize];
+        self.keypad
+            .get(key as usize)
+            .copied()
+            .ok_or(Chip8Error::InvalidKey {
+                key,
+                pc: self.fault_pc(),
+            })
     }
 
     // instruction set
 
+    // skips the next instruction, which on XO-CHIP may be the two word long F000 NNNN
+    fn skip(&mut self) -> Result<(), Chip8Error> {
+        if self.platform == Platform::XoChip
+            && self.read_word(self.pc as usize)? == LONG_LOAD_OPCODE
+        {
+            self.pc = self.pc.wrapping_add(2);
+        }
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
+    }
+
     fn clear_screen(&mut self) {
-        self.video_buffer = [0; VIDEO_WIDTH * VIDEO_HEIGHT];
+        for pixel in self.video_buffer.iter_mut() {
+            *pixel &= !self.plane_mask;
+        }
+    }
+
+    // moves the selected planes by (dx, dy) pixels, filling the gap with blank pixels
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let (width, height) = self.get_resolution();
+        let previous = self.video_buffer.clone();
+        for y in 0..height {
+            for x in 0..width {
+                let src_x = x as isize - dx;
+                let src_y = y as isize - dy;
+                let in_bounds =
+                    (0..width as isize).contains(&src_x) && (0..height as isize).contains(&src_y);
+                let scrolled = if in_bounds {
+                    previous[src_y as usize * width + src_x as usize] & self.plane_mask
+                } else {
+                    0
+                };
+                let pixel = &mut self.video_buffer[y * width + x];
+                *pixel = (*pixel & !self.plane_mask) | scrolled;
+            }
+        }
+    }
+
+    fn exit(&mut self) {
+        self.exited = true;
+    }
+
+    fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        let (width, height) = self.get_resolution();
+        self.video_buffer = vec![0; width * height];
     }
 
-    fn ret(&mut self) {
+    fn ret(&mut self) -> Result<(), Chip8Error> {
+        if self.sp == 0 {
+            return Err(Chip8Error::StackUnderflow {
+                pc: self.fault_pc(),
+            });
+        }
         self.sp -= 1;
         self.pc = self.stack[self.sp as usize];
+        Ok(())
     }
 
     fn jump(&mut self, address: u16) {
         self.pc = address;
     }
 
-    fn call(&mut self, address: u16) {
+    fn call(&mut self, address: u16) -> Result<(), Chip8Error> {
+        if self.sp as usize >= self.stack.len() {
+            return Err(Chip8Error::StackOverflow {
+                pc: self.fault_pc(),
+            });
+        }
         self.stack[self.sp as usize] = self.pc;
         self.sp += 1;
         self.pc = address;
+        Ok(())
     }
 
-    fn skip_if_equals_byte(&mut self, v_x: u8, byte: u8) {
+    fn skip_if_equals_byte(&mut self, v_x: u8, byte: u8) -> Result<(), Chip8Error> {
         if self.registers[v_x as usize] == byte {
-            self.pc += 2;
+            self.skip()?;
         }
+        Ok(())
     }
 
-    fn skip_if_not_equals_byte(&mut self, v_x: u8, byte: u8) {
+    fn skip_if_not_equals_byte(&mut self, v_x: u8, byte: u8) -> Result<(), Chip8Error> {
         if self.registers[v_x as usize] != byte {
-            self.pc += 2;
+            self.skip()?;
         }
+        Ok(())
     }
 
-    fn skip_if_equals_registers(&mut self, v_x: u8, v_y: u8) {
+    fn skip_if_equals_registers(&mut self, v_x: u8, v_y: u8) -> Result<(), Chip8Error> {
         if self.registers[v_x as usize] == self.registers[v_y as usize] {
-            self.pc += 2;
+            self.skip()?;
         }
+        Ok(())
     }
 
     fn load(&mut self, v_x: u8, byte: u8) {
@@ -294,64 +647,66 @@ impl Chip8 {
 
     fn or_registers(&mut self, v_x: u8, v_y: u8) {
         self.registers[v_x as usize] |= self.registers[v_y as usize];
+        if self.quirks.vf_reset {
+            self.registers[0xF] = 0;
+        }
     }
 
     fn and_registers(&mut self, v_x: u8, v_y: u8) {
         self.registers[v_x as usize] &= self.registers[v_y as usize];
+        if self.quirks.vf_reset {
+            self.registers[0xF] = 0;
+        }
     }
 
     fn xor_registers(&mut self, v_x: u8, v_y: u8) {
         self.registers[v_x as usize] ^= self.registers[v_y as usize];
+        if self.quirks.vf_reset {
+            self.registers[0xF] = 0;
+        }
     }
 
     fn add_registers(&mut self, v_x: u8, v_y: u8) {
-        let sum: u16 = self.registers[v_x as usize] as u16 + self.registers[v_y as usize] as u16;
-        if sum > 255u16 {
-            self.registers[0xF] = 1; // overflow flag
-        } else {
-            self.registers[0xF] = 0;
-        }
-        self.registers[v_x as usize] = (sum & 0x00FFu16) as u8;
+        let (sum, carry) =
+            self.registers[v_x as usize].overflowing_add(self.registers[v_y as usize]);
+        self.registers[v_x as usize] = sum;
+        self.registers[0xF] = carry as u8; // flag goes last in case VX is VF
     }
 
+    // VF is 1 when nothing was borrowed, so equal registers set it too
     fn sub_registers(&mut self, v_x: u8, v_y: u8) {
-        let sub: u16 =
-            (self.registers[v_x as usize] as u16).wrapping_sub(self.registers[v_y as usize] as u16);
-        if self.registers[v_x as usize] > self.registers[v_y as usize] {
-            self.registers[0xF] = 1;
-        } else {
-            self.registers[0xF] = 0;
-        }
-        self.registers[v_x as usize] = (sub & 0x00FFu16) as u8;
+        let (difference, borrow) =
+            self.registers[v_x as usize].overflowing_sub(self.registers[v_y as usize]);
+        self.registers[v_x as usize] = difference;
+        self.registers[0xF] = !borrow as u8;
     }
 
     fn subn_registers(&mut self, v_x: u8, v_y: u8) {
-        let sub: u16 =
-            (self.registers[v_y as usize] as u16).wrapping_sub(self.registers[v_y as usize] as u16);
-        if self.registers[v_y as usize] > self.registers[v_x as usize] {
-            self.registers[0xF] = 1;
-        } else {
-            self.registers[0xF] = 0;
-        }
-        self.registers[v_x as usize] = (sub & 0x00FFu16) as u8;
+        let (difference, borrow) =
+            self.registers[v_y as usize].overflowing_sub(self.registers[v_x as usize]);
+        self.registers[v_x as usize] = difference;
+        self.registers[0xF] = !borrow as u8;
     }
 
-    fn shr_register(&mut self, v_x: u8) {
-        let least_significant_bit: u8 = v_x & 0x01u8;
-        self.registers[0xF] = least_significant_bit;
-        self.registers[v_x as usize] >>= 1;
+    fn shr_register(&mut self, v_x: u8, v_y: u8) {
+        let source = if self.quirks.shift { v_x } else { v_y };
+        let value = self.registers[source as usize];
+        self.registers[v_x as usize] = value >> 1;
+        self.registers[0xF] = value & 0x01u8; // flag goes last in case VX is VF
     }
 
-    fn shl_register(&mut self, v_x: u8) {
-        let most_significant_bit: u8 = (v_x & 0xF0u8) >> 7;
-        self.registers[0xF] = most_significant_bit;
-        self.registers[v_x as usize] <<= 1;
+    fn shl_register(&mut self, v_x: u8, v_y: u8) {
+        let source = if self.quirks.shift { v_x } else { v_y };
+        let value = self.registers[source as usize];
+        self.registers[v_x as usize] = value << 1;
+        self.registers[0xF] = (value & 0x80u8) >> 7;
     }
 
-    fn sne(&mut self, v_x: u8, v_y: u8) {
+    fn sne(&mut self, v_x: u8, v_y: u8) -> Result<(), Chip8Error> {
         if self.registers[v_x as usize] != self.registers[v_y as usize] {
-            self.pc += 2;
+            self.skip()?;
         }
+        Ok(())
     }
 
     fn load_address_to_index(&mut self, addr: u16) {
@@ -359,51 +714,127 @@ impl Chip8 {
     }
 
     fn jump_v0(&mut self, addr: u16) {
-        self.pc = addr + self.registers[0 as usize] as u16;
+        // with the jump quirk BNNN is read as BXNN, offsetting by VX instead of V0
+        let offset_register = if self.quirks.jump {
+            (addr >> 8) as usize
+        } else {
+            0
+        };
+        self.pc = addr + self.registers[offset_register] as u16;
     }
 
     fn rnd_and_byte(&mut self, v_x: u8, byte: u8) {
-        let random_byte: u8 = self.rng.random();
+        let random_byte: u8 = self.rng.next_byte();
         self.registers[v_x as usize] = random_byte & byte;
     }
 
-    fn draw(&mut self, v_x: u8, v_y: u8, height: u8) {
-        let x: u16 = self.registers[v_x as usize] as u16;
-        let y: u16 = self.registers[v_y as usize] as u16;
-        self.registers[0xF] = 0; // collision flag reset
+    fn draw(&mut self, v_x: u8, v_y: u8, height: u8) -> Result<(), Chip8Error> {
+        if self.quirks.display_wait {
+            if !self.vblank {
+                // stall on this DXYN, it runs again once the next frame starts
+                self.pc = self.pc.wrapping_sub(2);
+                self.waiting_for_vblank = true;
+                return Ok(());
+            }
+            self.vblank = false;
+        }
+
+        let (screen_width, screen_height) = self.get_resolution();
+        let (screen_width, screen_height) = (screen_width as u16, screen_height as u16);
+
+        // DXY0 draws a 16x16 sprite on SUPER-CHIP and XO-CHIP, two bytes per row
+        let (sprite_width, sprite_height) = if height == 0 && self.platform != Platform::Chip8 {
+            (16u16, 16u8)
+        } else {
+            (8u16, height)
+        };
+
+        // the starting position always wraps, only the pixels past the edge get clipped
+        let x: u16 = self.registers[v_x as usize] as u16 % screen_width;
+        let y: u16 = self.registers[v_y as usize] as u16 % screen_height;
+
+        // SUPER-CHIP reports how many rows collided in high resolution mode
+        let count_rows = self.platform == Platform::SuperChip && self.hires;
+        let mut collided_rows = 0;
+        let bytes_per_row = (sprite_width / 8) as usize;
+
+        // on XO-CHIP each selected plane takes its own sprite, stored one after the other
+        let mut sprite_address = self.index as usize;
+        for plane in 0..2 {
+            let plane_bit = 1u8 << plane;
+            if self.plane_mask & plane_bit == 0 {
+                continue;
+            }
+
+            for row in 0..sprite_height {
+                let row_address = sprite_address + row as usize * bytes_per_row;
+                let sprite_row: u16 = if bytes_per_row == 2 {
+                    self.read_word(row_address)?
+                } else {
+                    self.read_byte(row_address)? as u16
+                };
+
+                let mut y_pos = y + row as u16;
+                if y_pos >= screen_height {
+                    if self.quirks.clip {
+                        if count_rows {
+                            collided_rows += 1; // rows clipped off the bottom count too
+                        }
+                        continue;
+                    }
+                    y_pos %= screen_height;
+                }
+
+                let mut row_collided = false;
+                for col in 0..sprite_width {
+                    let sprite_pixel = (sprite_row >> (sprite_width - 1 - col)) & 1;
+                    if sprite_pixel == 0 {
+                        continue;
+                    }
+                    let mut x_pos = x + col;
+                    if x_pos >= screen_width {
+                        if self.quirks.clip {
+                            continue;
+                        }
+                        x_pos %= screen_width;
+                    }
+                    let buffer_index = (y_pos * screen_width + x_pos) as usize;
 
-        for row in 0..height {
-            let sprite_byte = self.memory[self.index as usize + row as usize];
-            for col in 0..8 {
-                let sprite_pixel = (sprite_byte >> (7 - col)) & 1;
-                if sprite_pixel == 0 {
-                    continue;
+                    let screen_pixel = &mut self.video_buffer[buffer_index];
+                    if *screen_pixel & plane_bit != 0 {
+                        row_collided = true; // Collision detected
+                    }
+                    *screen_pixel ^= plane_bit; // Toggle pixel
                 }
-                let x_pos = (x + col) % VIDEO_WIDTH as u16;
-                let y_pos = (y + row as u16) % VIDEO_HEIGHT as u16;
-                let buffer_index = (y_pos * VIDEO_WIDTH as u16 + x_pos) as usize;
 
-                let screen_pixel = &mut self.video_buffer[buffer_index as usize];
-                if *screen_pixel == 0xFFFFFFFF {
-                    self.registers[0xF] = 1; // Collision detected
+                if row_collided {
+                    collided_rows += 1;
                 }
-                *screen_pixel ^= 0xFFFFFFFF; // Toggle pixel
             }
+
+            sprite_address += sprite_height as usize * bytes_per_row;
         }
+
+        self.registers[0xF] = if count_rows {
+            collided_rows
+        } else {
+            (collided_rows > 0) as u8
+        };
+        Ok(())
     }
 
-    fn skip_key(&mut self, v_x: u8) {
-        let key = self.registers[v_x as usize];
-        if self.keypad[key as usize] {
-            self.pc += 2;
+    fn skip_key(&mut self, v_x: u8) -> Result<(), Chip8Error> {
+        if self.key_pressed(v_x)? {
+            self.skip()?;
         }
+        Ok(())
     }
 
-    fn skip_not_key(&mut self, v_x: u8) {
-        let key = self.registers[v_x as usize];
-        if !self.keypad[key as usize] {
-            self.pc += 2;
+    fn skip_not_key(&mut self, v_x: u8) -> Result<(), Chip8Error> {
+        if !self.key_pressed(v_x)? {
+            self.skip()?;
         }
+        Ok(())
     }
 
     fn load_timer(&mut self, v_x: u8) {
@@ -412,14 +843,14 @@ impl Chip8 {
 
     fn load_key(&mut self, v_x: u8) {
         let mut key: i8 = -1;
-        for i in 0..15 {
+        for i in 0..16 {
             if self.keypad[i] {
                 key = i as i8;
                 break;
             }
         }
         if key == -1 {
-            self.pc -= 2;
+            self.pc = self.pc.wrapping_sub(2);
         } else {
             self.registers[v_x as usize] = key as u8;
         }
@@ -434,34 +865,256 @@ impl Chip8 {
     }
 
     fn add_to_index(&mut self, v_x: u8) {
-        self.index += self.registers[v_x as usize] as u16;
+        self.index = self.index.wrapping_add(self.registers[v_x as usize] as u16);
     }
 
     fn ld_digit_to_index(&mut self, v_x: u8) {
-        self.index = FONT_ADDRESS as u16 + (5 * self.registers[v_x as usize]) as u16;
+        let digit = self.registers[v_x as usize] & 0x0F;
+        self.index = FONT_ADDRESS as u16 + 5 * digit as u16;
     }
 
-    fn bcd_to_index(&mut self, v_x: u8) {
+    fn ld_big_digit_to_index(&mut self, v_x: u8) {
+        let digit = self.registers[v_x as usize] & 0x0F;
+        self.index = BIG_FONT_ADDRESS as u16 + 10 * digit as u16;
+    }
+
+    fn bcd_to_index(&mut self, v_x: u8) -> Result<(), Chip8Error> {
         let mut value: u8 = self.registers[v_x as usize];
 
-        self.memory[self.index as usize + 2] = value % 10;
+        self.write_byte(self.index as usize + 2, value % 10)?;
         value /= 10;
 
-        self.memory[self.index as usize + 1] = value % 10;
+        self.write_byte(self.index as usize + 1, value % 10)?;
         value /= 10;
 
-        self.memory[self.index as usize] = value % 10;
+        self.write_byte(self.index as usize, value % 10)
     }
 
-    fn store_to_index(&mut self, v_x: u8) {
+    fn store_to_index(&mut self, v_x: u8) -> Result<(), Chip8Error> {
         for i in 0..=v_x as usize {
-            self.memory[self.index as usize + i] = self.registers[i];
+            self.write_byte(self.index as usize + i, self.registers[i])?;
         }
+        if !self.quirks.load_store {
+            self.index = self.index.wrapping_add(v_x as u16 + 1);
+        }
+        Ok(())
     }
 
-    fn read_from_index(&mut self, v_x: u8) {
+    fn read_from_index(&mut self, v_x: u8) -> Result<(), Chip8Error> {
         for i in 0..=v_x as usize {
-            self.registers[i] = self.memory[self.index as usize + i];
+            self.registers[i] = self.read_byte(self.index as usize + i)?;
+        }
+        if !self.quirks.load_store {
+            self.index = self.index.wrapping_add(v_x as u16 + 1);
+        }
+        Ok(())
+    }
+
+    fn rpl_flag_count(&self, v_x: u8) -> usize {
+        // the HP48 only had 8 of these, XO-CHIP allows all 16 registers
+        match self.platform {
+            Platform::XoChip => v_x as usize + 1,
+            _ => (v_x as usize).min(7) + 1,
+        }
+    }
+
+    fn store_rpl_flags(&mut self, v_x: u8) {
+        let count = self.rpl_flag_count(v_x);
+        self.rpl_flags[..count].copy_from_slice(&self.registers[..count]);
+    }
+
+    fn read_rpl_flags(&mut self, v_x: u8) {
+        let count = self.rpl_flag_count(v_x);
+        self.registers[..count].copy_from_slice(&self.rpl_flags[..count]);
+    }
+
+    // XO-CHIP instructions
+
+    // 5XY2 and 5XY3 work in either direction, and never touch the index register
+    fn register_range(v_x: u8, v_y: u8) -> Vec<usize> {
+        if v_x <= v_y {
+            (v_x as usize..=v_y as usize).collect()
+        } else {
+            (v_y as usize..=v_x as usize).rev().collect()
+        }
+    }
+
+    fn store_range(&mut self, v_x: u8, v_y: u8) -> Result<(), Chip8Error> {
+        for (offset, register) in Self::register_range(v_x, v_y).into_iter().enumerate() {
+            self.write_byte(self.index as usize + offset, self.registers[register])?;
+        }
+        Ok(())
+    }
+
+    fn read_range(&mut self, v_x: u8, v_y: u8) -> Result<(), Chip8Error> {
+        for (offset, register) in Self::register_range(v_x, v_y).into_iter().enumerate() {
+            self.registers[register] = self.read_byte(self.index as usize + offset)?;
+        }
+        Ok(())
+    }
+
+    fn load_long_address_to_index(&mut self) -> Result<(), Chip8Error> {
+        self.index = self.read_word(self.pc as usize)?;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
+    }
+
+    fn select_planes(&mut self, planes: u8) {
+        self.plane_mask = planes & 0x3;
+    }
+
+    fn load_audio_pattern(&mut self) -> Result<(), Chip8Error> {
+        let mut pattern = [0u8; 16];
+        for (i, byte) in pattern.iter_mut().enumerate() {
+            *byte = self.read_byte(self.index as usize + i)?;
         }
+        self.audio_pattern = Some(pattern);
+        Ok(())
+    }
+
+    fn set_pitch(&mut self, v_x: u8) {
+        self.pitch = self.registers[v_x as usize];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a machine with `registers` set, after running `instruction` on it
+    fn execute(registers: &[(u8, u8)], instruction: Instruction) -> Chip8 {
+        let mut chip8 = Chip8::new(Platform::Chip8, Quirks::octo());
+        for &(register, value) in registers {
+            chip8.registers[register as usize] = value;
+        }
+        chip8.execute(instruction).unwrap();
+        chip8
+    }
+
+    #[test]
+    fn add_carries_into_vf() {
+        let chip8 = execute(&[(1, 0xFF), (2, 0x01)], Instruction::Add { x: 1, y: 2 });
+        assert_eq!((chip8.registers[1], chip8.registers[0xF]), (0x00, 1));
+        let chip8 = execute(&[(1, 0xFE), (2, 0x01)], Instruction::Add { x: 1, y: 2 });
+        assert_eq!((chip8.registers[1], chip8.registers[0xF]), (0xFF, 0));
+    }
+
+    #[test]
+    fn sub_sets_vf_unless_it_borrows() {
+        // VX, VY, then VX - VY and VF
+        for (x, y, result, flag) in [(5, 3, 2, 1), (3, 3, 0, 1), (3, 5, 0xFE, 0)] {
+            let chip8 = execute(&[(1, x), (2, y)], Instruction::Sub { x: 1, y: 2 });
+            assert_eq!((chip8.registers[1], chip8.registers[0xF]), (result, flag));
+        }
+    }
+
+    #[test]
+    fn subn_subtracts_vx_from_vy_and_sets_vf_unless_it_borrows() {
+        // VX, VY, then VY - VX and VF
+        for (x, y, result, flag) in [(3, 5, 2, 1), (3, 3, 0, 1), (5, 3, 0xFE, 0)] {
+            let chip8 = execute(&[(1, x), (2, y)], Instruction::SubN { x: 1, y: 2 });
+            assert_eq!((chip8.registers[1], chip8.registers[0xF]), (result, flag));
+        }
+    }
+
+    #[test]
+    fn the_flag_wins_when_vf_is_the_destination() {
+        let chip8 = execute(&[(0xF, 3), (2, 3)], Instruction::Sub { x: 0xF, y: 2 });
+        assert_eq!(chip8.registers[0xF], 1);
+        let chip8 = execute(&[(0xF, 3), (2, 5)], Instruction::SubN { x: 0xF, y: 2 });
+        assert_eq!(chip8.registers[0xF], 1);
+    }
+
+    #[test]
+    fn wait_key_sees_every_key() {
+        let mut chip8 = Chip8::new(Platform::Chip8, Quirks::octo());
+        chip8.pc = 0x202;
+        chip8.keypad[0xF] = true;
+        chip8.execute(Instruction::WaitKey { x: 3 }).unwrap();
+        assert_eq!((chip8.registers[3], chip8.pc), (0xF, 0x202));
+    }
+
+    #[test]
+    fn wait_key_repeats_until_a_key_is_down() {
+        let mut chip8 = Chip8::new(Platform::Chip8, Quirks::octo());
+        chip8.pc = 0x202;
+        chip8.execute(Instruction::WaitKey { x: 3 }).unwrap();
+        assert_eq!((chip8.registers[3], chip8.pc), (0, 0x200));
+    }
+
+    #[test]
+    fn font_digits_only_look_at_the_low_nibble() {
+        let chip8 = execute(&[(4, 0x0A)], Instruction::LoadDigit { x: 4 });
+        assert_eq!(chip8.index as usize, FONT_ADDRESS + 5 * 0xA);
+        let chip8 = execute(&[(4, 0xFF)], Instruction::LoadDigit { x: 4 });
+        assert_eq!(chip8.index as usize, FONT_ADDRESS + 5 * 0xF);
+    }
+
+    // a state with the payload rewritten by `edit`, header and checksum made to match
+    fn edited_state(chip8: &Chip8, edit: impl FnOnce(&mut Vec<u8>)) -> Vec<u8> {
+        let state = chip8.save_state();
+        let mut payload = state[10..state.len() - 4].to_vec();
+        edit(&mut payload);
+        let mut writer = StateWriter::new();
+        for byte in payload {
+            writer.u8(byte);
+        }
+        writer.finish()
+    }
+
+    #[test]
+    fn states_round_trip() {
+        let mut chip8 = Chip8::new(Platform::XoChip, Quirks::octo());
+        chip8
+            .load_program(&[0x60, 0x2A, 0x00, 0xFF, 0xA2, 0x34])
+            .unwrap();
+        for _ in 0..3 {
+            chip8.tick().unwrap();
+        }
+        chip8.keypad[7] = true;
+        let state = chip8.save_state();
+
+        let mut restored = Chip8::new(Platform::Chip8, Quirks::vip());
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.save_state(), state);
+        assert_eq!(
+            (restored.platform, restored.quirks),
+            (Platform::XoChip, Quirks::octo())
+        );
+        assert_eq!((restored.registers[0], restored.index), (0x2A, 0x234));
+        assert!(restored.hires);
+    }
+
+    #[test]
+    fn loading_a_state_stops_waiting_for_vblank() {
+        let state = Chip8::new(Platform::Chip8, Quirks::vip()).save_state();
+        let mut chip8 = Chip8::new(Platform::Chip8, Quirks::vip());
+        chip8.waiting_for_vblank = true;
+        chip8.load_state(&state).unwrap();
+        assert!(!chip8.is_waiting_for_vblank());
+    }
+
+    #[test]
+    fn states_with_impossible_fields_are_refused() {
+        let chip8 = Chip8::new(Platform::Chip8, Quirks::vip());
+        let platform = edited_state(&chip8, |payload| payload[0] = 3);
+        // platform, quirks, memory, pc, stack, sp, index, timers, then the video buffer
+        let hires = 2 + 4 + chip8.memory.len() + 2 + 32 + 2 + 2 + 2 + 4 + chip8.video_buffer.len();
+        let resolution = edited_state(&chip8, |payload| payload[hires] = 1);
+
+        let mut target = Chip8::new(Platform::SuperChip, Quirks::schip());
+        let before = target.save_state();
+        assert_eq!(
+            target.load_state(&platform),
+            Err(StateError::InvalidField { field: "platform" })
+        );
+        assert_eq!(
+            target.load_state(&resolution),
+            Err(StateError::InvalidField {
+                field: "video buffer"
+            })
+        );
+        // and the machine is left as it was
+        assert_eq!(target.save_state(), before);
     }
 }
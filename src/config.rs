@@ -0,0 +1,105 @@
+use rust_8::palette::{Palette, Rgb};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// the settings file, in toml. everything is optional:
+//
+//   [keymap]
+//   mode = "keycode"          # or "scancode", the default
+//   5 = ["W", "Space"]        # keypad key = SDL names of the host keys
+//
+//   [[controllers]]           # the first controller plugged in, then the second
+//   dpup = "1"                # SDL button name = keypad key
+//
+//   [palette]
+//   preset = "amber"          # monochrome, octo, green, amber or lcd
+//   colors = ["#000", "#FB0"] # background, plane 1, plane 2, both planes
+//
+//   [roms."pong.ch8".keymap]  # per rom, by file name
+//   1 = ["Up"]
+//   [[roms."pong.ch8".controllers]]
+//   dpup = "1"
+//   [roms."pong.ch8".palette]
+//   preset = "lcd"
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub keymap: KeymapConfig,
+    pub controllers: Vec<ControllerConfig>,
+    pub palette: PaletteConfig,
+    pub roms: HashMap<String, RomConfig>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RomConfig {
+    pub keymap: KeymapConfig,
+    pub controllers: Vec<ControllerConfig>,
+    pub palette: PaletteConfig,
+}
+
+// button name -> keypad key in hex
+pub type ControllerConfig = HashMap<String, String>;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct KeymapConfig {
+    pub mode: Option<String>,
+    // keypad key in hex -> host key names
+    #[serde(flatten)]
+    pub keys: HashMap<String, Vec<String>>,
+}
+
+// a preset, with any colors given replacing its own from the background on
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PaletteConfig {
+    pub preset: Option<String>,
+    pub colors: Vec<String>,
+}
+
+impl PaletteConfig {
+    // none if it doesn't set anything
+    pub fn palette(&self) -> Result<Option<Palette>, String> {
+        if self.preset.is_none() && self.colors.is_empty() {
+            return Ok(None);
+        }
+        let preset = match &self.preset {
+            Some(name) => {
+                Palette::from_name(name).ok_or_else(|| format!("Unknown preset {name}."))?
+            }
+            None => Palette::default(),
+        };
+        let colors = self
+            .colors
+            .iter()
+            .map(|hex| Rgb::from_hex(hex).ok_or_else(|| format!("{hex} isn't a color.")))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Some(preset.with_colors(&colors)))
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        toml::from_str(&text).map_err(|err| err.to_string())
+    }
+
+    // $XDG_CONFIG_HOME/rust-8/config.toml, or ~/.config/rust-8/config.toml
+    pub fn default_path() -> Option<PathBuf> {
+        let folder = match env::var_os("XDG_CONFIG_HOME") {
+            Some(folder) => PathBuf::from(folder),
+            None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+        Some(folder.join("rust-8").join("config.toml"))
+    }
+
+    // the settings for a rom, looked up by its file name
+    pub fn rom(&self, rom_path: &str) -> Option<&RomConfig> {
+        let name = Path::new(rom_path).file_name()?.to_str()?;
+        self.roms.get(name)
+    }
+}
@@ -0,0 +1,202 @@
+// looks roms up by their SHA-1 in a database laid out like the community chip-8-database,
+// which knows the platform, tickrate, quirks, keys and colors most roms were made for.
+// one is compiled in from database/, and another copy can be loaded at runtime.
+
+use crate::palette::{Palette, Rgb};
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+use crate::rom::RomOptions;
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const BUNDLED_HASHES: &str = include_str!("../database/sha1-hashes.json");
+const BUNDLED_PROGRAMS: &str = include_str!("../database/programs.json");
+
+#[derive(Deserialize)]
+struct Program {
+    title: String,
+    // keyed by SHA-1, a program can have several versions
+    #[serde(default)]
+    roms: HashMap<String, RomEntry>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct RomEntry {
+    // in order of preference
+    platforms: Vec<String>,
+    tickrate: Option<u32>,
+    // quirks that differ from the platform's usual ones, keyed by platform id
+    quirky_platforms: HashMap<String, QuirkOverrides>,
+    keys: BTreeMap<String, u8>,
+    colors: Option<Colors>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct QuirkOverrides {
+    shift: Option<bool>,
+    memory_leave_i_unchanged: Option<bool>,
+    wrap: Option<bool>,
+    jump: Option<bool>,
+    vblank: Option<bool>,
+    logic: Option<bool>,
+}
+
+impl QuirkOverrides {
+    fn apply(&self, quirks: &mut Quirks) {
+        let overrides = [
+            (self.shift, &mut quirks.shift),
+            (self.memory_leave_i_unchanged, &mut quirks.load_store),
+            (self.wrap.map(|wrap| !wrap), &mut quirks.clip),
+            (self.jump, &mut quirks.jump),
+            (self.vblank, &mut quirks.display_wait),
+            (self.logic, &mut quirks.vf_reset),
+        ];
+        for (value, quirk) in overrides {
+            if let Some(value) = value {
+                *quirk = value;
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Colors {
+    // background first, then the planes
+    pixels: Vec<String>,
+}
+
+// the database's platform ids that we can run, with their quirks and usual tickrate
+fn platform(id: &str) -> Option<(Platform, Quirks, u32)> {
+    match id {
+        "originalChip8" | "hybridVIP" => Some((Platform::Chip8, Quirks::vip(), 15)),
+        "modernChip8" => Some((Platform::Chip8, Quirks::modern(), 12)),
+        "chip48" => Some((Platform::Chip8, Quirks::schip(), 30)),
+        "superchip1" | "superchip" => Some((Platform::SuperChip, Quirks::schip(), 30)),
+        "xochip" => Some((Platform::XoChip, Quirks::octo(), 100)),
+        _ => None,
+    }
+}
+
+impl RomEntry {
+    fn options(&self) -> RomOptions {
+        let mut options = RomOptions {
+            tickrate: self.tickrate,
+            key_hints: self.keys.clone(),
+            ..RomOptions::default()
+        };
+
+        let supported = self
+            .platforms
+            .iter()
+            .find_map(|id| Some((id, platform(id)?)));
+        if let Some((id, (platform, mut quirks, tickrate))) = supported {
+            if let Some(overrides) = self.quirky_platforms.get(id) {
+                overrides.apply(&mut quirks);
+            }
+            options.platform = Some(platform);
+            options.quirks = Some(quirks);
+            options.tickrate = options.tickrate.or(Some(tickrate));
+        }
+
+        if let Some(colors) = self
+            .colors
+            .as_ref()
+            .filter(|colors| !colors.pixels.is_empty())
+        {
+            let mut palette = Palette::default();
+            for (color, hex) in palette.0.iter_mut().zip(&colors.pixels) {
+                *color = Rgb::from_hex(hex).unwrap_or(*color);
+            }
+            options.palette = Some(palette);
+        }
+
+        options
+    }
+}
+
+// what the database knows about a rom
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatabaseEntry {
+    pub title: String,
+    pub options: RomOptions,
+}
+
+pub struct RomDatabase {
+    // SHA-1 in lowercase hex -> index into `programs`
+    hashes: HashMap<String, usize>,
+    programs: Vec<Program>,
+}
+
+impl RomDatabase {
+    pub fn bundled() -> Self {
+        Self::parse(BUNDLED_HASHES, BUNDLED_PROGRAMS).expect("The bundled rom database is broken.")
+    }
+
+    // a folder holding sha1-hashes.json and programs.json, like the community database's
+    pub fn load(folder: &Path) -> io::Result<Self> {
+        let hashes = fs::read_to_string(folder.join("sha1-hashes.json"))?;
+        let programs = fs::read_to_string(folder.join("programs.json"))?;
+        Self::parse(&hashes, &programs)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    fn parse(hashes: &str, programs: &str) -> serde_json::Result<Self> {
+        Ok(Self {
+            hashes: serde_json::from_str(hashes)?,
+            programs: serde_json::from_str(programs)?,
+        })
+    }
+
+    pub fn lookup(&self, program: &[u8]) -> Option<DatabaseEntry> {
+        let hash: String = Sha1::digest(program)
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        let entry = self.programs.get(*self.hashes.get(&hash)?)?;
+        let rom = entry.roms.get(&hash)?;
+        Some(DatabaseEntry {
+            title: entry.title.clone(),
+            options: rom.options(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(platforms: &str, program: &[u8]) -> Option<DatabaseEntry> {
+        let hash: String = Sha1::digest(program)
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        let hashes = format!(r#"{{"{hash}": 0}}"#);
+        let programs =
+            format!(r#"[{{"title": "Test", "roms": {{"{hash}": {{"platforms": {platforms}}}}}}}]"#);
+        RomDatabase::parse(&hashes, &programs)
+            .unwrap()
+            .lookup(program)
+    }
+
+    #[test]
+    fn modern_chip8_clips_sprites() {
+        let entry = lookup(r#"["modernChip8"]"#, &[0x12, 0x00]).unwrap();
+        assert_eq!(entry.options.platform, Some(Platform::Chip8));
+        assert_eq!(entry.options.quirks, Some(Quirks::modern()));
+        assert!(entry.options.quirks.unwrap().clip);
+    }
+
+    #[test]
+    fn the_first_platform_we_can_run_wins() {
+        let entry = lookup(r#"["megachip8", "superchip"]"#, &[0x12, 0x00]).unwrap();
+        assert_eq!(entry.options.platform, Some(Platform::SuperChip));
+        assert_eq!(entry.options.quirks, Some(Quirks::schip()));
+    }
+}
@@ -0,0 +1,392 @@
+use crate::chip8::Chip8;
+use crate::disassembler::{disassemble, disassemble_at, Syntax};
+use crate::error::Chip8Error;
+use crate::instruction::Instruction;
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+const HELP: &str = "\
+commands (addresses are hex, counts are decimal):
+  c, continue          run until a breakpoint or fault
+  p, pause             stop a running rom
+  s, step [count]      run one instruction, or `count` of them
+  n, next              like step, but runs over a whole CALL
+  o, out               run until the current subroutine returns
+  b, break <addr>      set a breakpoint
+  d, delete [addr]     delete a breakpoint, or all of them
+  bl, breakpoints      list breakpoints
+  r, regs              show the registers, index, timers and pc
+  stack                show the call stack
+  x, mem <addr> [len]  dump memory
+  l, dis [addr] [count]  disassemble, around the pc by default
+  h, help              show this list";
+
+// what the run loop needs from a debugger, so the terminal one below and the gdb stub can
+// be attached the same way
+pub trait DebugHook {
+    // called once per frame, before any instructions run
+    fn update(&mut self, chip8: &mut Chip8);
+    // while paused the run loop keeps presenting frames but runs no instructions
+    fn is_paused(&self) -> bool;
+    // called before every instruction while running, false means stop before it
+    fn before_tick(&mut self, chip8: &Chip8) -> bool;
+    // the rom crashed, the hook is expected to pause rather than end the run
+    fn fault(&mut self, chip8: &Chip8, err: Chip8Error);
+}
+
+// breakpoints as both debuggers keep them. resuming from a breakpoint has to run the
+// instruction it sits on instead of stopping there again.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Breakpoints {
+    addresses: BTreeSet<u16>,
+    resuming: bool,
+}
+
+impl Breakpoints {
+    pub fn insert(&mut self, address: u16) {
+        self.addresses.insert(address);
+    }
+
+    pub fn remove(&mut self, address: u16) -> bool {
+        self.addresses.remove(&address)
+    }
+
+    pub fn clear(&mut self) {
+        self.addresses.clear();
+    }
+
+    pub fn contains(&self, address: u16) -> bool {
+        self.addresses.contains(&address)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u16> + '_ {
+        self.addresses.iter().copied()
+    }
+
+    pub fn resume(&mut self) {
+        self.resuming = true;
+    }
+
+    // called before every instruction, true if we should stop before the one at `pc`
+    pub fn hit(&mut self, pc: u16) -> bool {
+        let resuming = std::mem::take(&mut self.resuming);
+        !resuming && self.addresses.contains(&pc)
+    }
+}
+
+// runs one instruction for a paused debugger. frames don't pass while paused, so stepping
+// past a display wait ends one.
+pub(crate) fn step_instruction(chip8: &mut Chip8) -> Result<(), Chip8Error> {
+    if chip8.is_waiting_for_vblank() {
+        chip8.tick_clock();
+    }
+    chip8.tick()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Paused,
+    Running,
+    // running until the CALL made with `sp` entries on the stack returns to `pc`
+    StepOver { pc: u16, sp: u16 },
+    // running until the stack drops below `sp` entries
+    StepOut { sp: u16 },
+}
+
+// a small gdb-like debugger driven from the terminal. commands are read on their own
+// thread, so the frontend keeps rendering (and stays responsive) while we are paused.
+pub struct Debugger {
+    mode: Mode,
+    breakpoints: Breakpoints,
+    lines: Receiver<String>,
+}
+
+impl Debugger {
+    // starts paused, so breakpoints can be set before the rom runs
+    pub fn new() -> Self {
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        println!("rust-8 debugger, type `help` for a list of commands");
+        prompt();
+
+        Self {
+            mode: Mode::Paused,
+            breakpoints: Breakpoints::default(),
+            lines,
+        }
+    }
+
+    fn pause(&mut self, chip8: &Chip8, reason: &str) {
+        self.mode = Mode::Paused;
+        println!();
+        println!("{reason}");
+        show_location(chip8);
+        prompt();
+    }
+
+    fn resume(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.breakpoints.resume();
+    }
+
+    fn run_command(&mut self, chip8: &mut Chip8, line: &str) {
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            prompt();
+            return;
+        };
+        let args: Vec<&str> = words.collect();
+        // addresses past the end of memory are as good as no address
+        let memory_size = chip8.get_memory().len();
+        let address_arg = |i: usize| {
+            args.get(i)
+                .and_then(|arg| parse_address(arg))
+                .filter(|&address| address < memory_size)
+        };
+        let count_arg = |i: usize| args.get(i).and_then(|arg| arg.parse::<usize>().ok());
+
+        match command {
+            "h" | "help" => println!("{HELP}"),
+            "c" | "continue" => {
+                self.resume(Mode::Running);
+                return;
+            }
+            "p" | "pause" => {
+                if !self.is_paused() {
+                    self.pause(chip8, "paused");
+                    return;
+                }
+            }
+            "s" | "step" => {
+                self.step(chip8, count_arg(0).unwrap_or(1));
+                return;
+            }
+            "n" | "next" => {
+                let pc = chip8.get_pc();
+                match disassemble_at(chip8.get_memory(), pc as usize, Syntax::Cowgod) {
+                    Some(line) if matches!(line.instruction, Instruction::Call { .. }) => {
+                        self.resume(Mode::StepOver {
+                            pc: pc.wrapping_add(2),
+                            sp: chip8.get_sp(),
+                        });
+                        return;
+                    }
+                    _ => {
+                        self.step(chip8, 1);
+                        return;
+                    }
+                }
+            }
+            "o" | "out" => {
+                if chip8.get_sp() == 0 {
+                    println!("not inside a subroutine");
+                } else {
+                    self.resume(Mode::StepOut { sp: chip8.get_sp() });
+                    return;
+                }
+            }
+            "b" | "break" => match address_arg(0) {
+                Some(address) => {
+                    self.breakpoints.insert(address as u16);
+                    println!("breakpoint at 0x{address:03X}");
+                }
+                None => println!("usage: break <addr>"),
+            },
+            "d" | "delete" => match (args.first(), address_arg(0)) {
+                (None, _) => self.breakpoints.clear(),
+                (Some(_), Some(address)) => {
+                    if !self.breakpoints.remove(address as u16) {
+                        println!("no breakpoint at 0x{address:03X}");
+                    }
+                }
+                (Some(_), None) => println!("usage: delete [addr]"),
+            },
+            "bl" | "breakpoints" => {
+                if self.breakpoints.is_empty() {
+                    println!("no breakpoints");
+                }
+                for address in self.breakpoints.iter() {
+                    println!("0x{address:03X}");
+                }
+            }
+            "r" | "regs" => show_registers(chip8),
+            "stack" => {
+                if chip8.get_stack().is_empty() {
+                    println!("the stack is empty");
+                }
+                for (depth, address) in chip8.get_stack().iter().enumerate().rev() {
+                    println!("#{depth} return to 0x{address:03X}");
+                }
+            }
+            "x" | "mem" => match address_arg(0) {
+                Some(address) => show_memory(chip8, address, count_arg(1).unwrap_or(64)),
+                None => println!("usage: mem <addr> [len]"),
+            },
+            "l" | "dis" => {
+                // start a few instructions before the pc so we can see how we got here
+                let address =
+                    address_arg(0).unwrap_or_else(|| (chip8.get_pc() as usize).saturating_sub(8));
+                self.show_disassembly(chip8, address, count_arg(1).unwrap_or(12));
+            }
+            _ => println!("unknown command `{command}`, type `help` for a list"),
+        }
+
+        prompt();
+    }
+
+    fn step(&mut self, chip8: &mut Chip8, count: usize) {
+        for _ in 0..count {
+            if let Err(err) = step_instruction(chip8) {
+                self.fault(chip8, err);
+                return;
+            }
+        }
+        show_location(chip8);
+        prompt();
+    }
+
+    fn show_disassembly(&self, chip8: &Chip8, address: usize, count: usize) {
+        let pc = chip8.get_pc() as usize;
+        for line in disassemble(chip8.get_memory(), address, count, Syntax::Cowgod) {
+            let marker = if line.address == pc { "->" } else { "  " };
+            let breakpoint = if self.breakpoints.contains(line.address as u16) {
+                "*"
+            } else {
+                " "
+            };
+            println!("{marker}{breakpoint} 0x{:03X}  {}", line.address, line.text);
+        }
+    }
+}
+
+impl DebugHook for Debugger {
+    fn is_paused(&self) -> bool {
+        self.mode == Mode::Paused
+    }
+
+    fn update(&mut self, chip8: &mut Chip8) {
+        loop {
+            match self.lines.try_recv() {
+                Ok(line) => {
+                    self.run_command(chip8, &line);
+                    // let the rom run before reading on, so `continue` then `regs` means something
+                    if !self.is_paused() {
+                        break;
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    // stdin is gone and nobody can resume us, so let the rom run
+                    self.mode = Mode::Running;
+                    break;
+                }
+            }
+        }
+    }
+
+    fn before_tick(&mut self, chip8: &Chip8) -> bool {
+        let pc = chip8.get_pc();
+        let sp = chip8.get_sp();
+        let breakpoint = self.breakpoints.hit(pc);
+
+        let reason = match self.mode {
+            Mode::Paused => return false,
+            Mode::StepOver {
+                pc: return_pc,
+                sp: call_sp,
+            } if pc == return_pc && sp == call_sp => Some("stepped over"),
+            Mode::StepOut { sp: call_sp } if sp < call_sp => Some("stepped out"),
+            _ if breakpoint => Some("breakpoint"),
+            _ => None,
+        };
+
+        match reason {
+            Some(reason) => {
+                self.pause(chip8, reason);
+                false
+            }
+            None => true,
+        }
+    }
+
+    fn fault(&mut self, chip8: &Chip8, err: Chip8Error) {
+        self.pause(chip8, &format!("fault: {err}"));
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn prompt() {
+    print!("(rust-8) ");
+    let _ = io::stdout().flush();
+}
+
+// addresses are hex, with or without a 0x prefix
+fn parse_address(arg: &str) -> Option<usize> {
+    let digits = arg
+        .strip_prefix("0x")
+        .or_else(|| arg.strip_prefix("0X"))
+        .unwrap_or(arg);
+    usize::from_str_radix(digits, 16).ok()
+}
+
+fn show_location(chip8: &Chip8) {
+    let pc = chip8.get_pc();
+    match disassemble_at(chip8.get_memory(), pc as usize, Syntax::Cowgod) {
+        Some(line) => println!("0x{pc:03X}  {}", line.text),
+        None => println!("0x{pc:03X}  <outside of memory>"),
+    }
+}
+
+fn show_registers(chip8: &Chip8) {
+    println!(
+        "PC 0x{:03X}  I 0x{:03X}  SP {}  DT {}  ST {}",
+        chip8.get_pc(),
+        chip8.get_index(),
+        chip8.get_sp(),
+        chip8.get_delay_timer(),
+        chip8.get_sound_timer()
+    );
+    for row in chip8.get_registers().chunks(8).enumerate() {
+        let (row, registers) = row;
+        let line: Vec<String> = registers
+            .iter()
+            .enumerate()
+            .map(|(i, value)| format!("V{:X} {value:02X}", row * 8 + i))
+            .collect();
+        println!("{}", line.join("  "));
+    }
+}
+
+fn show_memory(chip8: &Chip8, address: usize, length: usize) {
+    let memory = chip8.get_memory();
+    let end = address.saturating_add(length).min(memory.len());
+    if address >= end {
+        println!("0x{address:03X} is outside of memory");
+        return;
+    }
+    for (row, bytes) in memory[address..end].chunks(16).enumerate() {
+        let hex: Vec<String> = bytes.iter().map(|byte| format!("{byte:02X}")).collect();
+        println!("0x{:03X}  {}", address + row * 16, hex.join(" "));
+    }
+}
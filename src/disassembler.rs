@@ -0,0 +1,229 @@
+use crate::chip8::PROGRAM_START;
+use crate::instruction::{decode, Instruction};
+
+// how mnemonics are written out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Syntax {
+    // the uppercase mnemonics from Cowgod's technical reference, e.g. "LD V0, 0x05"
+    #[default]
+    Cowgod,
+    // Octo's assembly language, e.g. "v0 := 0x05"
+    Octo,
+}
+
+impl Syntax {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "cowgod" => Some(Self::Cowgod),
+            "octo" => Some(Self::Octo),
+            _ => None,
+        }
+    }
+}
+
+// one disassembled instruction as it sits in memory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub address: usize,
+    pub instruction: Instruction,
+    // 2 bytes, or 4 for F000 NNNN
+    pub length: usize,
+    pub text: String,
+}
+
+// returns None when there isn't a whole instruction left at `address`
+pub fn disassemble_at(memory: &[u8], address: usize, syntax: Syntax) -> Option<Line> {
+    let word = |at: usize| -> Option<u16> {
+        Some((*memory.get(at)? as u16) << 8 | *memory.get(at + 1)? as u16)
+    };
+
+    let instruction = decode(word(address)?);
+    let long_address = match instruction {
+        Instruction::LoadLongIndex => word(address + 2),
+        _ => None,
+    };
+    let (length, text) = match (long_address, syntax) {
+        (Some(long_address), Syntax::Cowgod) => (4, format!("LD I, 0x{long_address:04X}")),
+        (Some(long_address), Syntax::Octo) => (4, format!("i := long 0x{long_address:04X}")),
+        (None, _) => (2, format_instruction(instruction, syntax)),
+    };
+
+    Some(Line {
+        address,
+        instruction,
+        length,
+        text,
+    })
+}
+
+// disassembles `count` instructions in a row, starting at `address`
+pub fn disassemble(memory: &[u8], address: usize, count: usize, syntax: Syntax) -> Vec<Line> {
+    let mut lines = Vec::with_capacity(count);
+    let mut address = address;
+    while lines.len() < count {
+        let Some(line) = disassemble_at(memory, address, syntax) else {
+            break;
+        };
+        address += line.length;
+        lines.push(line);
+    }
+    lines
+}
+
+// a full listing of a rom loaded at 0x200, one "address  raw bytes  mnemonic" row per
+// instruction. with `follow` only what is reachable from 0x200 is decoded and everything
+// else, usually sprites, is listed as bytes.
+pub fn disassemble_rom(rom: &[u8], syntax: Syntax, follow: bool) -> String {
+    let mut memory = vec![0; PROGRAM_START];
+    memory.extend_from_slice(rom);
+    let end = memory.len();
+    let code = match follow {
+        true => trace(&memory),
+        false => vec![true; end],
+    };
+
+    let mut out = String::new();
+    let mut address = PROGRAM_START;
+    while address < end {
+        let line = match code[address] {
+            true => disassemble_at(&memory, address, syntax),
+            false => None,
+        };
+        let (length, text) = match line {
+            Some(line) => (line.length, line.text),
+            None => {
+                // runs of data are split every 4 bytes and wherever code starts again
+                let length = (address..end)
+                    .take(4)
+                    .take_while(|&at| at == address || !code[at])
+                    .count();
+                (
+                    length,
+                    format_data(&memory[address..address + length], syntax),
+                )
+            }
+        };
+
+        let raw: String = memory[address..address + length]
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect();
+        out.push_str(&format!("0x{address:03X}  {raw:<8}  {text}\n"));
+        address += length;
+    }
+    out
+}
+
+// marks every byte that is part of an instruction reachable from 0x200. computed jumps
+// (BNNN) can't be followed, so only their base address is taken as code.
+fn trace(memory: &[u8]) -> Vec<bool> {
+    let mut code = vec![false; memory.len()];
+    let mut pending = vec![PROGRAM_START];
+
+    while let Some(address) = pending.pop() {
+        if address < PROGRAM_START || code.get(address) != Some(&false) {
+            continue;
+        }
+        let Some(line) = disassemble_at(memory, address, Syntax::Cowgod) else {
+            continue;
+        };
+        if matches!(line.instruction, Instruction::Unknown { .. }) {
+            continue;
+        }
+        code[address..address + line.length].fill(true);
+
+        let next = address + line.length;
+        match line.instruction {
+            Instruction::Jump { nnn } => pending.push(nnn as usize),
+            Instruction::JumpV0 { nnn } => pending.push(nnn as usize),
+            Instruction::Call { nnn } => pending.extend([nnn as usize, next]),
+            Instruction::Return | Instruction::Exit => {}
+            Instruction::SkipIfEqualsByte { .. }
+            | Instruction::SkipIfNotEqualsByte { .. }
+            | Instruction::SkipIfEqualsRegisters { .. }
+            | Instruction::SkipIfNotEqualsRegisters { .. }
+            | Instruction::SkipKey { .. }
+            | Instruction::SkipNotKey { .. } => {
+                // skips step over the whole of a following F000 NNNN
+                let skipped =
+                    disassemble_at(memory, next, Syntax::Cowgod).map_or(2, |line| line.length);
+                pending.extend([next, next + skipped]);
+            }
+            _ => pending.push(next),
+        }
+    }
+    code
+}
+
+fn format_instruction(instruction: Instruction, syntax: Syntax) -> String {
+    match syntax {
+        Syntax::Cowgod => instruction.to_string(),
+        Syntax::Octo => octo(instruction),
+    }
+}
+
+fn format_data(bytes: &[u8], syntax: Syntax) -> String {
+    let bytes: Vec<String> = bytes.iter().map(|byte| format!("0x{byte:02X}")).collect();
+    match syntax {
+        Syntax::Cowgod => format!("DB {}", bytes.join(", ")),
+        Syntax::Octo => bytes.join(" "),
+    }
+}
+
+// octo writes skips as the condition under which the next instruction runs, so they read
+// the opposite way round to the opcodes
+fn octo(instruction: Instruction) -> String {
+    match instruction {
+        Instruction::ClearScreen => "clear".to_string(),
+        Instruction::Return => "return".to_string(),
+        Instruction::ScrollDown { n } => format!("scroll-down {n}"),
+        Instruction::ScrollUp { n } => format!("scroll-up {n}"),
+        Instruction::ScrollRight => "scroll-right".to_string(),
+        Instruction::ScrollLeft => "scroll-left".to_string(),
+        Instruction::Exit => "exit".to_string(),
+        Instruction::LowRes => "lores".to_string(),
+        Instruction::HighRes => "hires".to_string(),
+        Instruction::Jump { nnn } => format!("jump 0x{nnn:03X}"),
+        Instruction::Call { nnn } => format!(":call 0x{nnn:03X}"),
+        Instruction::SkipIfEqualsByte { x, nn } => format!("if v{x:x} != 0x{nn:02X} then"),
+        Instruction::SkipIfNotEqualsByte { x, nn } => format!("if v{x:x} == 0x{nn:02X} then"),
+        Instruction::SkipIfEqualsRegisters { x, y } => format!("if v{x:x} != v{y:x} then"),
+        Instruction::StoreRange { x, y } => format!("save v{x:x} - v{y:x}"),
+        Instruction::ReadRange { x, y } => format!("load v{x:x} - v{y:x}"),
+        Instruction::Load { x, nn } => format!("v{x:x} := 0x{nn:02X}"),
+        Instruction::AddToRegister { x, nn } => format!("v{x:x} += 0x{nn:02X}"),
+        Instruction::LoadRegister { x, y } => format!("v{x:x} := v{y:x}"),
+        Instruction::Or { x, y } => format!("v{x:x} |= v{y:x}"),
+        Instruction::And { x, y } => format!("v{x:x} &= v{y:x}"),
+        Instruction::Xor { x, y } => format!("v{x:x} ^= v{y:x}"),
+        Instruction::Add { x, y } => format!("v{x:x} += v{y:x}"),
+        Instruction::Sub { x, y } => format!("v{x:x} -= v{y:x}"),
+        Instruction::ShiftRight { x, y } => format!("v{x:x} >>= v{y:x}"),
+        Instruction::SubN { x, y } => format!("v{x:x} =- v{y:x}"),
+        Instruction::ShiftLeft { x, y } => format!("v{x:x} <<= v{y:x}"),
+        Instruction::SkipIfNotEqualsRegisters { x, y } => format!("if v{x:x} == v{y:x} then"),
+        Instruction::LoadIndex { nnn } => format!("i := 0x{nnn:03X}"),
+        Instruction::JumpV0 { nnn } => format!("jump0 0x{nnn:03X}"),
+        Instruction::Random { x, nn } => format!("v{x:x} := random 0x{nn:02X}"),
+        Instruction::Draw { x, y, n } => format!("sprite v{x:x} v{y:x} {n}"),
+        Instruction::SkipKey { x } => format!("if v{x:x} -key then"),
+        Instruction::SkipNotKey { x } => format!("if v{x:x} key then"),
+        Instruction::LoadLongIndex => "i := long".to_string(),
+        Instruction::SelectPlanes { x } => format!("plane {x}"),
+        Instruction::LoadAudioPattern => "audio".to_string(),
+        Instruction::LoadDelay { x } => format!("v{x:x} := delay"),
+        Instruction::WaitKey { x } => format!("v{x:x} := key"),
+        Instruction::SetDelay { x } => format!("delay := v{x:x}"),
+        Instruction::SetSound { x } => format!("buzzer := v{x:x}"),
+        Instruction::AddToIndex { x } => format!("i += v{x:x}"),
+        Instruction::LoadDigit { x } => format!("i := hex v{x:x}"),
+        Instruction::LoadBigDigit { x } => format!("i := bighex v{x:x}"),
+        Instruction::Bcd { x } => format!("bcd v{x:x}"),
+        Instruction::SetPitch { x } => format!("pitch := v{x:x}"),
+        Instruction::StoreRegisters { x } => format!("save v{x:x}"),
+        Instruction::ReadRegisters { x } => format!("load v{x:x}"),
+        Instruction::StoreFlags { x } => format!("saveflags v{x:x}"),
+        Instruction::ReadFlags { x } => format!("loadflags v{x:x}"),
+        Instruction::Unknown { opcode } => format!("0x{:02X} 0x{:02X}", opcode >> 8, opcode & 0xFF),
+    }
+}
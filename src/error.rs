@@ -0,0 +1,74 @@
+use std::fmt;
+
+// everything a misbehaving rom can do to the interpreter. `pc` is always the address of the
+// instruction that caused the fault, so a debugger can point right at it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chip8Error {
+    StackOverflow { pc: u16 },
+    StackUnderflow { pc: u16 },
+    MemoryOutOfBounds { addr: usize, pc: u16 },
+    InvalidKey { key: u8, pc: u16 },
+    UnknownOpcode { opcode: u16, pc: u16 },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::StackOverflow { pc } => write!(f, "stack overflow at {pc:#05X}"),
+            Self::StackUnderflow { pc } => {
+                write!(f, "return with an empty stack at {pc:#05X}")
+            }
+            Self::MemoryOutOfBounds { addr, pc } => {
+                write!(f, "memory access out of bounds ({addr:#06X}) at {pc:#05X}")
+            }
+            Self::InvalidKey { key, pc } => write!(f, "invalid key {key:#04X} at {pc:#05X}"),
+            Self::UnknownOpcode { opcode, pc } => {
+                write!(f, "unknown opcode {opcode:#06X} at {pc:#05X}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {}
+
+// why a save state couldn't be restored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateError {
+    NotAState,
+    UnsupportedVersion { version: u16 },
+    ChecksumMismatch,
+    Truncated,
+    InvalidField { field: &'static str },
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotAState => write!(f, "not a rust-8 save state"),
+            Self::UnsupportedVersion { version } => {
+                write!(f, "save state version {version} is not supported")
+            }
+            Self::ChecksumMismatch => write!(f, "save state is corrupted (bad checksum)"),
+            Self::Truncated => write!(f, "save state is truncated"),
+            Self::InvalidField { field } => write!(f, "save state has an invalid {field}"),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+// why a piece of source couldn't be assembled. lines and columns count from 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssembleError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for AssembleError {}
@@ -0,0 +1,269 @@
+use crate::chip8::Chip8;
+use crate::debugger::DebugHook;
+use crate::error::Chip8Error;
+use crate::rewind::Rewind;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+// the pieces a frontend has to provide. the run loop below only talks to these, so
+// windowed, headless and terminal frontends all drive the interpreter the same way.
+
+pub trait DisplaySink {
+    // `buffer` holds width * height pixels, one bit per bitplane
+    fn draw(&mut self, buffer: &[u8], width: usize, height: usize);
+}
+
+// things the user can ask for besides pressing keypad keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Quit,
+    SaveState(u8),
+    LoadState(u8),
+    // sent when the rewind key goes down and again when it comes back up
+    Rewind(bool),
+}
+
+pub trait InputSource {
+    // updates the keypad and returns any other commands the user gave since the last call
+    fn process_input(&mut self, keypad: &mut [bool; 16]) -> Vec<Command>;
+}
+
+pub trait AudioSink {
+    fn update(&mut self, chip8: &Chip8);
+}
+
+pub trait Clock {
+    // time elapsed since some fixed starting point
+    fn now(&self) -> Duration;
+    fn sleep(&mut self, duration: Duration);
+}
+
+// for frontends that have no way of making noise
+pub struct NoAudio;
+
+impl AudioSink for NoAudio {
+    fn update(&mut self, _chip8: &Chip8) {}
+}
+
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+pub const CLOCK_RATE: u32 = 60;
+// instructions run per 60 Hz frame, about 700 per second
+pub const DEFAULT_TICKRATE: u32 = 12;
+
+pub struct Runner {
+    tickrate: u32,
+    frame_period: Duration,
+    // save state slot N is stored at "<prefix>.stateN"
+    save_state_prefix: Option<PathBuf>,
+    rewind: Option<Rewind>,
+    rewinding: bool,
+    debugger: Option<Box<dyn DebugHook>>,
+}
+
+impl Runner {
+    pub fn new(tickrate: u32) -> Self {
+        Self {
+            tickrate,
+            frame_period: Duration::from_nanos(1_000_000_000 / CLOCK_RATE as u64),
+            save_state_prefix: None,
+            rewind: None,
+            rewinding: false,
+            debugger: None,
+        }
+    }
+
+    // enables the save state commands, usually with the rom path as the prefix
+    pub fn with_save_states(mut self, prefix: impl Into<PathBuf>) -> Self {
+        self.save_state_prefix = Some(prefix.into());
+        self
+    }
+
+    // records every frame so holding the rewind key can step back through them
+    pub fn with_rewind(mut self, rewind: Rewind) -> Self {
+        self.rewind = Some(rewind);
+        self
+    }
+
+    // faults pause the debugger instead of ending the run
+    pub fn with_debugger(mut self, debugger: impl DebugHook + 'static) -> Self {
+        self.debugger = Some(Box::new(debugger));
+        self
+    }
+
+    fn save_state_path(&self, slot: u8) -> Option<PathBuf> {
+        let prefix = self.save_state_prefix.as_ref()?;
+        let mut path = prefix.clone().into_os_string();
+        path.push(format!(".state{slot}"));
+        Some(path.into())
+    }
+
+    fn save_state(&self, chip8: &Chip8, slot: u8) {
+        let Some(path) = self.save_state_path(slot) else {
+            return;
+        };
+        match fs::write(&path, chip8.save_state()) {
+            Ok(()) => eprintln!("Saved state {slot} to {}", path.display()),
+            Err(err) => eprintln!("Couldn't save state {slot}: {err}"),
+        }
+    }
+
+    fn load_state(&self, chip8: &mut Chip8, slot: u8) {
+        let Some(path) = self.save_state_path(slot) else {
+            return;
+        };
+        let result = fs::read(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|state| {
+                keep_keypad(chip8, |chip8| chip8.load_state(&state)).map_err(|err| err.to_string())
+            });
+        match result {
+            Ok(()) => eprintln!("Loaded state {slot} from {}", path.display()),
+            Err(err) => eprintln!("Couldn't load state {slot}: {err}"),
+        }
+    }
+
+    // runs a single 60 Hz frame: input, a batch of instructions, the timers, then audio and
+    // video. returns false once the input asks to quit or the rom exits on its own.
+    pub fn run_frame<F: DisplaySink + InputSource>(
+        &mut self,
+        chip8: &mut Chip8,
+        frontend: &mut F,
+        audio: &mut impl AudioSink,
+    ) -> Result<bool, Chip8Error> {
+        for command in frontend.process_input(chip8.get_keypad()) {
+            match command {
+                Command::Quit => return Ok(false),
+                Command::SaveState(slot) => self.save_state(chip8, slot),
+                Command::LoadState(slot) => self.load_state(chip8, slot),
+                Command::Rewind(rewinding) => self.rewinding = rewinding,
+            }
+        }
+
+        match &mut self.rewind {
+            Some(rewind) if self.rewinding => {
+                // stays on the oldest frame once the history runs out
+                keep_keypad(chip8, |chip8| rewind.step_back(chip8));
+                Self::present(chip8, frontend, audio);
+                return Ok(true);
+            }
+            _ => {}
+        }
+
+        if let Some(debugger) = &mut self.debugger {
+            debugger.update(chip8);
+            if debugger.is_paused() {
+                Self::present(chip8, frontend, audio);
+                return Ok(true);
+            }
+        }
+
+        // where this frame starts, only worth remembering if it runs anything. a paused
+        // debugger or one stopping right away would fill the history with the same frame.
+        let start = self.rewind.as_ref().map(|_| chip8.save_state());
+        let mut executed = 0;
+        for _ in 0..self.tickrate {
+            match &mut self.debugger {
+                Some(debugger) => {
+                    if !debugger.before_tick(chip8) {
+                        break;
+                    }
+                    if let Err(err) = chip8.tick() {
+                        debugger.fault(chip8, err);
+                        break;
+                    }
+                }
+                None => chip8.tick()?,
+            }
+            executed += 1;
+            if chip8.has_exited() {
+                return Ok(false);
+            }
+            // the rest of the frame's instructions would only wait too
+            if chip8.is_waiting_for_vblank() {
+                break;
+            }
+        }
+        if let (Some(rewind), Some(start)) = (&mut self.rewind, start.filter(|_| executed > 0)) {
+            rewind.record(start);
+        }
+        chip8.tick_clock();
+
+        Self::present(chip8, frontend, audio);
+        Ok(true)
+    }
+
+    fn present(chip8: &Chip8, display: &mut impl DisplaySink, audio: &mut impl AudioSink) {
+        audio.update(chip8);
+
+        let (width, height) = chip8.get_resolution();
+        display.draw(chip8.get_video_buffer(), width, height);
+    }
+
+    // runs frames in real time until run_frame says to stop. the screen and the keyboard
+    // usually come from the same window, so one value provides both.
+    pub fn run<F: DisplaySink + InputSource>(
+        &mut self,
+        chip8: &mut Chip8,
+        frontend: &mut F,
+        audio: &mut impl AudioSink,
+        clock: &mut impl Clock,
+    ) -> Result<(), Chip8Error> {
+        let mut next_frame = clock.now();
+
+        while self.run_frame(chip8, frontend, audio)? {
+            next_frame += self.frame_period;
+            let now = clock.now();
+            if next_frame > now {
+                clock.sleep(next_frame - now);
+            } else {
+                next_frame = now; // running behind, don't try to catch up in a burst
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// states hold the keypad as it was when they were taken, but the keys down now are whatever
+// the frontend last said. frontends only report changes, so anything held in the state
+// would stay stuck down until pressed and let go again.
+fn keep_keypad<T>(chip8: &mut Chip8, restore: impl FnOnce(&mut Chip8) -> T) -> T {
+    let keypad = *chip8.get_keypad();
+    let result = restore(chip8);
+    *chip8.get_keypad() = keypad;
+    result
+}
+
+impl Default for Runner {
+    fn default() -> Self {
+        Self::new(DEFAULT_TICKRATE)
+    }
+}
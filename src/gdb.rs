@@ -0,0 +1,652 @@
+use crate::chip8::Chip8;
+use crate::debugger::{step_instruction, Breakpoints, DebugHook};
+use crate::error::Chip8Error;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+
+// the register file as gdb sees it: V0-VF, then I, PC and SP, as described to gdb by the
+// target description below. I and PC go over the wire little endian.
+const REGISTER_COUNT: usize = 19;
+const INDEX_REGISTER: usize = 16;
+const PC_REGISTER: usize = 17;
+const SP_REGISTER: usize = 18;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.rust-8.chip8">
+    <reg name="v0" bitsize="8" type="uint8"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+// signals reported in stop replies
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+// serves the gdb remote serial protocol on a local port, one client at a time. the rom runs
+// freely until a client attaches, which stops it like gdb expects.
+pub struct GdbStub {
+    listener: TcpListener,
+    client: Option<TcpStream>,
+    // bytes received that don't make up a whole packet yet
+    pending: Vec<u8>,
+    paused: bool,
+    breakpoints: Breakpoints,
+}
+
+impl GdbStub {
+    pub fn bind(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            client: None,
+            pending: Vec::new(),
+            paused: false,
+            breakpoints: Breakpoints::default(),
+        })
+    }
+
+    pub fn local_port(&self) -> io::Result<u16> {
+        Ok(self.listener.local_addr()?.port())
+    }
+
+    fn accept(&mut self) {
+        match self.listener.accept() {
+            Ok((stream, address)) => {
+                if stream.set_nonblocking(true).is_err() {
+                    return;
+                }
+                eprintln!("gdb attached from {address}");
+                self.client = Some(stream);
+                self.pending.clear();
+                self.paused = true;
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+            Err(err) => eprintln!("gdb stub couldn't accept a connection: {err}"),
+        }
+    }
+
+    // drops the client and lets the rom carry on without it
+    fn detach(&mut self) {
+        eprintln!("gdb detached");
+        self.client = None;
+        self.pending.clear();
+        self.breakpoints.clear();
+        self.paused = false;
+    }
+
+    // reads whatever the client sent, returns false once it has gone away
+    fn receive(&mut self) -> bool {
+        let Some(client) = &mut self.client else {
+            return false;
+        };
+        let mut buffer = [0; 4096];
+        loop {
+            match client.read(&mut buffer) {
+                Ok(0) => return false,
+                Ok(read) => self.pending.extend_from_slice(&buffer[..read]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => return true,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(_) => return false,
+            }
+        }
+    }
+
+    fn send_raw(&mut self, bytes: &[u8]) {
+        let Some(client) = &mut self.client else {
+            return;
+        };
+        // the socket only needs to be non-blocking for reads, replies are small
+        let sent = client
+            .set_nonblocking(false)
+            .and_then(|()| client.write_all(bytes))
+            .and_then(|()| client.set_nonblocking(true));
+        if sent.is_err() {
+            self.detach();
+        }
+    }
+
+    fn send(&mut self, data: &str) {
+        let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        self.send_raw(format!("${data}#{checksum:02x}").as_bytes());
+    }
+
+    fn stop(&mut self, signal: u8) {
+        self.paused = true;
+        self.send(&format!("S{signal:02x}"));
+    }
+
+    // splits complete packets off the front of `pending`. acks are dropped, a lone 0x03
+    // is gdb asking us to interrupt the rom.
+    fn next_packet(&mut self) -> Option<Packet> {
+        loop {
+            let start = self.pending.first()?;
+            match start {
+                b'$' => break,
+                0x03 => {
+                    self.pending.remove(0);
+                    return Some(Packet::Interrupt);
+                }
+                _ => {
+                    self.pending.remove(0);
+                }
+            }
+        }
+
+        let end = self.pending.iter().position(|&byte| byte == b'#')?;
+        if self.pending.len() < end + 3 {
+            return None;
+        }
+        let packet: Vec<u8> = self.pending.drain(..end + 3).collect();
+        let data = &packet[1..end];
+        let checksum = std::str::from_utf8(&packet[end + 1..])
+            .ok()
+            .and_then(|digits| u8::from_str_radix(digits, 16).ok());
+        let expected = data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        if checksum != Some(expected) {
+            self.send_raw(b"-");
+            return Some(Packet::Corrupt);
+        }
+
+        self.send_raw(b"+");
+        Some(Packet::Command(String::from_utf8_lossy(data).into_owned()))
+    }
+
+    fn handle(&mut self, chip8: &mut Chip8, packet: &str) {
+        let (command, args) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+        let reply = match command {
+            "?" => format!("S{SIGTRAP:02x}"),
+            "g" => (0..REGISTER_COUNT)
+                .map(|register| read_register(chip8, register))
+                .collect(),
+            "G" => {
+                let mut rest = args;
+                for register in 0..REGISTER_COUNT {
+                    let width = register_width(register) * 2;
+                    let Some(value) = rest.get(..width) else {
+                        break;
+                    };
+                    write_register(chip8, register, value);
+                    rest = &rest[width..];
+                }
+                "OK".to_string()
+            }
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(register) if register < REGISTER_COUNT => read_register(chip8, register),
+                _ => "E01".to_string(),
+            },
+            "P" => {
+                let written = args.split_once('=').and_then(|(register, value)| {
+                    let register = usize::from_str_radix(register, 16).ok()?;
+                    (register < REGISTER_COUNT).then(|| write_register(chip8, register, value))
+                });
+                match written {
+                    Some(()) => "OK".to_string(),
+                    None => "E01".to_string(),
+                }
+            }
+            "m" => match parse_range(args).and_then(|range| read_memory(chip8, range)) {
+                Some(hex) => hex,
+                None => "E01".to_string(),
+            },
+            "M" => {
+                let written = args
+                    .split_once(':')
+                    .and_then(|(range, data)| write_memory(chip8, parse_range(range)?, data));
+                match written {
+                    Some(()) => "OK".to_string(),
+                    None => "E01".to_string(),
+                }
+            }
+            "c" => {
+                if let Some(address) = parse_hex(args) {
+                    chip8.set_pc(address as u16);
+                }
+                self.paused = false;
+                self.breakpoints.resume();
+                return;
+            }
+            "s" => {
+                if let Some(address) = parse_hex(args) {
+                    chip8.set_pc(address as u16);
+                }
+                match step_instruction(chip8) {
+                    Ok(()) => self.stop(SIGTRAP),
+                    Err(err) => self.fault(chip8, err),
+                }
+                return;
+            }
+            "Z" | "z" => {
+                // only software breakpoints, anything else gets the empty "unsupported" reply
+                let mut fields = args.split(',');
+                match (fields.next(), fields.next().and_then(parse_hex)) {
+                    (Some("0"), Some(address)) if address >= chip8.get_memory().len() => {
+                        "E01".to_string()
+                    }
+                    (Some("0"), Some(address)) => {
+                        if command == "Z" {
+                            self.breakpoints.insert(address as u16);
+                        } else {
+                            self.breakpoints.remove(address as u16);
+                        }
+                        "OK".to_string()
+                    }
+                    _ => String::new(),
+                }
+            }
+            "H" => "OK".to_string(),
+            "q" => self.query(args),
+            "D" => {
+                self.send("OK");
+                self.detach();
+                return;
+            }
+            "k" => {
+                self.detach();
+                return;
+            }
+            _ => String::new(),
+        };
+        self.send(&reply);
+    }
+
+    fn query(&self, query: &str) -> String {
+        if query.starts_with("Supported") {
+            return "PacketSize=1000;qXfer:features:read+".to_string();
+        }
+        if let Some(args) = query.strip_prefix("Xfer:features:read:target.xml:") {
+            let Some((offset, length)) = parse_range(args) else {
+                return "E01".to_string();
+            };
+            let start = offset.min(TARGET_XML.len());
+            let end = start.saturating_add(length).min(TARGET_XML.len());
+            let marker = if end == TARGET_XML.len() { 'l' } else { 'm' };
+            return format!("{marker}{}", &TARGET_XML[start..end]);
+        }
+        match query {
+            "Attached" => "1".to_string(),
+            "C" => "QC1".to_string(),
+            "fThreadInfo" => "m1".to_string(),
+            "sThreadInfo" => "l".to_string(),
+            _ => String::new(),
+        }
+    }
+}
+
+enum Packet {
+    Command(String),
+    Interrupt,
+    Corrupt,
+}
+
+impl DebugHook for GdbStub {
+    fn update(&mut self, chip8: &mut Chip8) {
+        if self.client.is_none() {
+            self.accept();
+        }
+        if self.client.is_none() {
+            return;
+        }
+        if !self.receive() {
+            self.detach();
+            return;
+        }
+
+        while let Some(packet) = self.next_packet() {
+            match packet {
+                Packet::Command(packet) => self.handle(chip8, &packet),
+                Packet::Interrupt if !self.paused => self.stop(SIGINT),
+                Packet::Interrupt | Packet::Corrupt => {}
+            }
+            if self.client.is_none() {
+                return;
+            }
+        }
+    }
+
+    fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    fn before_tick(&mut self, chip8: &Chip8) -> bool {
+        if self.paused {
+            return false;
+        }
+        if self.breakpoints.hit(chip8.get_pc()) {
+            self.stop(SIGTRAP);
+            return false;
+        }
+        true
+    }
+
+    fn fault(&mut self, _chip8: &Chip8, err: Chip8Error) {
+        if self.client.is_none() {
+            // nobody to tell, so behave like a breakpoint the next client will find
+            eprintln!("The rom crashed: {err}");
+            self.paused = true;
+            return;
+        }
+        let signal = match err {
+            Chip8Error::UnknownOpcode { .. } => SIGILL,
+            _ => SIGSEGV,
+        };
+        self.stop(signal);
+    }
+}
+
+fn register_width(register: usize) -> usize {
+    match register {
+        INDEX_REGISTER | PC_REGISTER => 2,
+        _ => 1,
+    }
+}
+
+fn read_register(chip8: &Chip8, register: usize) -> String {
+    match register {
+        INDEX_REGISTER => to_hex(&chip8.get_index().to_le_bytes()),
+        PC_REGISTER => to_hex(&chip8.get_pc().to_le_bytes()),
+        SP_REGISTER => format!("{:02x}", chip8.get_sp()),
+        _ => format!("{:02x}", chip8.get_registers()[register]),
+    }
+}
+
+fn write_register(chip8: &mut Chip8, register: usize, hex: &str) {
+    let Some(bytes) = from_hex(hex) else {
+        return;
+    };
+    let value = bytes
+        .iter()
+        .rev()
+        .fold(0u16, |value, &byte| value << 8 | byte as u16);
+    match register {
+        INDEX_REGISTER => chip8.set_index(value),
+        PC_REGISTER => chip8.set_pc(value),
+        SP_REGISTER => chip8.set_sp(value),
+        _ => chip8.get_registers_mut()[register] = value as u8,
+    }
+}
+
+fn read_memory(chip8: &Chip8, (address, length): (usize, usize)) -> Option<String> {
+    let bytes = chip8
+        .get_memory()
+        .get(address..address.checked_add(length)?)?;
+    Some(to_hex(bytes))
+}
+
+fn write_memory(chip8: &mut Chip8, (address, length): (usize, usize), hex: &str) -> Option<()> {
+    let bytes = from_hex(hex)?;
+    if bytes.len() != length {
+        return None;
+    }
+    let memory = chip8.get_memory_mut();
+    memory
+        .get_mut(address..address.checked_add(length)?)?
+        .copy_from_slice(&bytes);
+    Some(())
+}
+
+fn parse_hex(text: &str) -> Option<usize> {
+    usize::from_str_radix(text, 16).ok()
+}
+
+// "addr,length" as used by the memory packets
+fn parse_range(text: &str) -> Option<(usize, usize)> {
+    let (address, length) = text.split_once(',')?;
+    Some((parse_hex(address)?, parse_hex(length)?))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    // LD V0, 1 / LD V1, 2 / LD V2, 3 / JP 0x206
+    const ROM: [u8; 8] = [0x60, 0x01, 0x61, 0x02, 0x62, 0x03, 0x12, 0x06];
+
+    struct Session {
+        stub: GdbStub,
+        chip8: Chip8,
+        client: TcpStream,
+        received: Vec<u8>,
+    }
+
+    impl Session {
+        // a stub on a free port with a client attached to it
+        fn attach() -> Self {
+            let mut stub = GdbStub::bind(0).unwrap();
+            let client =
+                TcpStream::connect((Ipv4Addr::LOCALHOST, stub.local_port().unwrap())).unwrap();
+            client.set_nonblocking(true).unwrap();
+            let mut chip8 = Chip8::new(Default::default(), Default::default());
+            chip8.load_program(&ROM).unwrap();
+
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while stub.client.is_none() {
+                assert!(
+                    Instant::now() < deadline,
+                    "the stub never accepted the client"
+                );
+                stub.update(&mut chip8);
+            }
+            Self {
+                stub,
+                chip8,
+                client,
+                received: Vec::new(),
+            }
+        }
+
+        fn send_raw(&mut self, bytes: &[u8]) {
+            self.client.write_all(bytes).unwrap();
+        }
+
+        fn send(&mut self, data: &str) {
+            let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+            self.send_raw(format!("${data}#{checksum:02x}").as_bytes());
+        }
+
+        // lets the stub handle what was sent until `done` finds what we are waiting for
+        fn wait_for<T>(&mut self, mut done: impl FnMut(&mut Vec<u8>) -> Option<T>) -> T {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            loop {
+                self.stub.update(&mut self.chip8);
+                let mut buffer = [0; 4096];
+                match self.client.read(&mut buffer) {
+                    Ok(read) => self.received.extend_from_slice(&buffer[..read]),
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                    Err(err) => panic!("the stub went away: {err}"),
+                }
+                if let Some(found) = done(&mut self.received) {
+                    return found;
+                }
+                assert!(
+                    Instant::now() < deadline,
+                    "no reply, got {:?}",
+                    self.received
+                );
+            }
+        }
+
+        // the data of the next packet from the stub, skipping acks
+        fn reply(&mut self) -> String {
+            self.wait_for(|received| {
+                let start = received.iter().position(|&byte| byte == b'$')?;
+                let end = start + received[start..].iter().position(|&byte| byte == b'#')?;
+                if received.len() < end + 3 {
+                    return None;
+                }
+                let packet: Vec<u8> = received.drain(..end + 3).collect();
+                Some(String::from_utf8(packet[start + 1..end].to_vec()).unwrap())
+            })
+        }
+
+        // for packets that get no reply, like continue
+        fn acked(&mut self) {
+            self.wait_for(|received| {
+                let ack = received.iter().position(|&byte| byte == b'+')?;
+                received.drain(..=ack);
+                Some(())
+            })
+        }
+
+        fn command(&mut self, data: &str) -> String {
+            self.send(data);
+            self.reply()
+        }
+
+        // what the run loop does while the stub lets the rom run
+        fn run(&mut self, instructions: usize) {
+            for _ in 0..instructions {
+                if !self.stub.before_tick(&self.chip8) {
+                    return;
+                }
+                self.chip8.tick().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn attaching_stops_the_rom() {
+        let mut session = Session::attach();
+        assert!(session.stub.is_paused());
+        assert_eq!(session.command("?"), "S05");
+        assert!(!session.stub.before_tick(&session.chip8));
+    }
+
+    #[test]
+    fn reads_all_registers() {
+        let mut session = Session::attach();
+        session.chip8.get_registers_mut()[0xF] = 0xAB;
+        session.chip8.set_index(0x0345);
+        let registers = session.command("g");
+        // 16 V registers, I, PC and SP
+        assert_eq!(registers.len(), (16 + 2 + 2 + 1) * 2);
+        assert_eq!(&registers[30..32], "ab");
+        assert_eq!(&registers[32..36], "4503");
+        assert_eq!(&registers[36..40], "0002");
+        assert_eq!(&registers[40..], "00");
+    }
+
+    #[test]
+    fn reads_and_writes_single_registers() {
+        let mut session = Session::attach();
+        assert_eq!(session.command("p11"), "0002");
+        assert_eq!(session.command("P3=7f"), "OK");
+        assert_eq!(session.chip8.get_registers()[3], 0x7F);
+        assert_eq!(session.command("P10=3412"), "OK");
+        assert_eq!(session.chip8.get_index(), 0x1234);
+        assert_eq!(session.command("p13"), "E01");
+    }
+
+    #[test]
+    fn reads_and_writes_memory() {
+        let mut session = Session::attach();
+        assert_eq!(session.command("m200,4"), "60016102");
+        assert_eq!(session.command("M300,2:abcd"), "OK");
+        assert_eq!(&session.chip8.get_memory()[0x300..0x302], &[0xAB, 0xCD]);
+        assert_eq!(session.command("M300,2:ab"), "E01");
+        assert_eq!(session.command("mffff,10"), "E01");
+        assert_eq!(session.command("m200,ffffffffffffffff"), "E01");
+    }
+
+    #[test]
+    fn steps_one_instruction() {
+        let mut session = Session::attach();
+        assert_eq!(session.command("s"), "S05");
+        assert_eq!(session.chip8.get_pc(), 0x202);
+        assert_eq!(session.chip8.get_registers()[0], 1);
+        assert!(session.stub.is_paused());
+    }
+
+    #[test]
+    fn continues_to_a_breakpoint() {
+        let mut session = Session::attach();
+        assert_eq!(session.command("Z0,204,2"), "OK");
+        assert_eq!(session.command("Z0,10204,2"), "E01");
+        session.send("c");
+        session.acked();
+        assert!(!session.stub.is_paused());
+
+        session.run(100);
+        assert_eq!(session.reply(), "S05");
+        assert_eq!(session.chip8.get_pc(), 0x204);
+        assert_eq!(session.chip8.get_registers()[1], 2);
+        assert_eq!(session.chip8.get_registers()[2], 0);
+
+        // continuing from the breakpoint doesn't stop on it again, and removed ones are gone
+        assert_eq!(session.command("z0,204,2"), "OK");
+        session.send("c");
+        session.acked();
+        session.run(100);
+        assert!(!session.stub.is_paused());
+        assert_eq!(session.chip8.get_registers()[2], 3);
+    }
+
+    #[test]
+    fn interrupt_stops_a_running_rom() {
+        let mut session = Session::attach();
+        session.send("c");
+        session.acked();
+        session.run(10);
+        assert!(!session.stub.is_paused());
+
+        session.send_raw(&[0x03]);
+        assert_eq!(session.reply(), "S02");
+        assert!(session.stub.is_paused());
+    }
+
+    #[test]
+    fn bad_checksums_are_refused() {
+        let mut session = Session::attach();
+        session.send_raw(b"$g#00");
+        session.wait_for(|received| received.contains(&b'-').then_some(()));
+        assert!(!session.received.contains(&b'$'));
+        // the next good packet still goes through
+        assert_eq!(session.command("?"), "S05");
+    }
+
+    #[test]
+    fn serves_the_target_description() {
+        let mut session = Session::attach();
+        let start = session.command("qXfer:features:read:target.xml:0,10");
+        assert_eq!(start, format!("m{}", &TARGET_XML[..0x10]));
+        let rest = session.command("qXfer:features:read:target.xml:10,ffffffffffffffff");
+        assert_eq!(rest, format!("l{}", &TARGET_XML[0x10..]));
+    }
+}
@@ -0,0 +1,209 @@
+use crate::platform::Platform;
+use std::fmt;
+
+// one decoded chip-8 instruction, named after the Chip8 method that executes it.
+// x and y are register indices, the rest are the immediate operands straight from the opcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    ClearScreen,                               // 00E0
+    Return,                                    // 00EE
+    ScrollDown { n: u8 },                      // 00CN
+    ScrollUp { n: u8 },                        // 00DN
+    ScrollRight,                               // 00FB
+    ScrollLeft,                                // 00FC
+    Exit,                                      // 00FD
+    LowRes,                                    // 00FE
+    HighRes,                                   // 00FF
+    Jump { nnn: u16 },                         // 1NNN
+    Call { nnn: u16 },                         // 2NNN
+    SkipIfEqualsByte { x: u8, nn: u8 },        // 3XNN
+    SkipIfNotEqualsByte { x: u8, nn: u8 },     // 4XNN
+    SkipIfEqualsRegisters { x: u8, y: u8 },    // 5XY0
+    StoreRange { x: u8, y: u8 },               // 5XY2
+    ReadRange { x: u8, y: u8 },                // 5XY3
+    Load { x: u8, nn: u8 },                    // 6XNN
+    AddToRegister { x: u8, nn: u8 },           // 7XNN
+    LoadRegister { x: u8, y: u8 },             // 8XY0
+    Or { x: u8, y: u8 },                       // 8XY1
+    And { x: u8, y: u8 },                      // 8XY2
+    Xor { x: u8, y: u8 },                      // 8XY3
+    Add { x: u8, y: u8 },                      // 8XY4
+    Sub { x: u8, y: u8 },                      // 8XY5
+    ShiftRight { x: u8, y: u8 },               // 8XY6
+    SubN { x: u8, y: u8 },                     // 8XY7
+    ShiftLeft { x: u8, y: u8 },                // 8XYE
+    SkipIfNotEqualsRegisters { x: u8, y: u8 }, // 9XY0
+    LoadIndex { nnn: u16 },                    // ANNN
+    JumpV0 { nnn: u16 },                       // BNNN
+    Random { x: u8, nn: u8 },                  // CXNN
+    Draw { x: u8, y: u8, n: u8 },              // DXYN
+    SkipKey { x: u8 },                         // EX9E
+    SkipNotKey { x: u8 },                      // EXA1
+    LoadLongIndex,                             // F000 NNNN, the address is the next word
+    SelectPlanes { x: u8 },                    // FX01
+    LoadAudioPattern,                          // F002
+    LoadDelay { x: u8 },                       // FX07
+    WaitKey { x: u8 },                         // FX0A
+    SetDelay { x: u8 },                        // FX15
+    SetSound { x: u8 },                        // FX18
+    AddToIndex { x: u8 },                      // FX1E
+    LoadDigit { x: u8 },                       // FX29
+    LoadBigDigit { x: u8 },                    // FX30
+    Bcd { x: u8 },                             // FX33
+    SetPitch { x: u8 },                        // FX3A
+    StoreRegisters { x: u8 },                  // FX55
+    ReadRegisters { x: u8 },                   // FX65
+    StoreFlags { x: u8 },                      // FX75
+    ReadFlags { x: u8 },                       // FX85
+    Unknown { opcode: u16 },
+}
+
+pub fn decode(opcode: u16) -> Instruction {
+    // we start by capturing and storing the nibbles (4 bit)
+    let first_nibble: u8 = (opcode >> 12) as u8; // the first nibble
+    let x: u8 = ((opcode >> 8) & 0x000Fu16) as u8; // the second nibble
+    let y: u8 = ((opcode >> 4) & 0x000Fu16) as u8; // the third nibble
+    let n: u8 = (opcode & 0x000Fu16) as u8; // the fourth nibble
+    let nn: u8 = (opcode & 0x00FFu16) as u8; // second byte
+    let nnn: u16 = opcode & 0x0FFFu16; // second, third, fourth nibbles
+
+    match (first_nibble, x, nn, n) {
+        (0x0, 0x0, 0xE0, _) => Instruction::ClearScreen,
+        (0x0, 0x0, 0xEE, _) => Instruction::Return,
+        (0x0, 0x0, 0xC0..=0xCF, _) => Instruction::ScrollDown { n },
+        (0x0, 0x0, 0xD0..=0xDF, _) => Instruction::ScrollUp { n },
+        (0x0, 0x0, 0xFB, _) => Instruction::ScrollRight,
+        (0x0, 0x0, 0xFC, _) => Instruction::ScrollLeft,
+        (0x0, 0x0, 0xFD, _) => Instruction::Exit,
+        (0x0, 0x0, 0xFE, _) => Instruction::LowRes,
+        (0x0, 0x0, 0xFF, _) => Instruction::HighRes,
+        (0x1, _, _, _) => Instruction::Jump { nnn },
+        (0x2, _, _, _) => Instruction::Call { nnn },
+        (0x3, _, _, _) => Instruction::SkipIfEqualsByte { x, nn },
+        (0x4, _, _, _) => Instruction::SkipIfNotEqualsByte { x, nn },
+        (0x5, _, _, 0x0) => Instruction::SkipIfEqualsRegisters { x, y },
+        (0x5, _, _, 0x2) => Instruction::StoreRange { x, y },
+        (0x5, _, _, 0x3) => Instruction::ReadRange { x, y },
+        (0x6, _, _, _) => Instruction::Load { x, nn },
+        (0x7, _, _, _) => Instruction::AddToRegister { x, nn },
+        (0x8, _, _, 0x0) => Instruction::LoadRegister { x, y },
+        (0x8, _, _, 0x1) => Instruction::Or { x, y },
+        (0x8, _, _, 0x2) => Instruction::And { x, y },
+        (0x8, _, _, 0x3) => Instruction::Xor { x, y },
+        (0x8, _, _, 0x4) => Instruction::Add { x, y },
+        (0x8, _, _, 0x5) => Instruction::Sub { x, y },
+        (0x8, _, _, 0x6) => Instruction::ShiftRight { x, y },
+        (0x8, _, _, 0x7) => Instruction::SubN { x, y },
+        (0x8, _, _, 0xE) => Instruction::ShiftLeft { x, y },
+        (0x9, _, _, 0x0) => Instruction::SkipIfNotEqualsRegisters { x, y },
+        (0xA, _, _, _) => Instruction::LoadIndex { nnn },
+        (0xB, _, _, _) => Instruction::JumpV0 { nnn },
+        (0xC, _, _, _) => Instruction::Random { x, nn },
+        (0xD, _, _, _) => Instruction::Draw { x, y, n },
+        (0xE, _, 0x9E, _) => Instruction::SkipKey { x },
+        (0xE, _, 0xA1, _) => Instruction::SkipNotKey { x },
+        (0xF, 0x0, 0x00, _) => Instruction::LoadLongIndex,
+        (0xF, _, 0x01, _) => Instruction::SelectPlanes { x },
+        (0xF, 0x0, 0x02, _) => Instruction::LoadAudioPattern,
+        (0xF, _, 0x07, _) => Instruction::LoadDelay { x },
+        (0xF, _, 0x0A, _) => Instruction::WaitKey { x },
+        (0xF, _, 0x15, _) => Instruction::SetDelay { x },
+        (0xF, _, 0x18, _) => Instruction::SetSound { x },
+        (0xF, _, 0x1E, _) => Instruction::AddToIndex { x },
+        (0xF, _, 0x29, _) => Instruction::LoadDigit { x },
+        (0xF, _, 0x30, _) => Instruction::LoadBigDigit { x },
+        (0xF, _, 0x33, _) => Instruction::Bcd { x },
+        (0xF, _, 0x3A, _) => Instruction::SetPitch { x },
+        (0xF, _, 0x55, _) => Instruction::StoreRegisters { x },
+        (0xF, _, 0x65, _) => Instruction::ReadRegisters { x },
+        (0xF, _, 0x75, _) => Instruction::StoreFlags { x },
+        (0xF, _, 0x85, _) => Instruction::ReadFlags { x },
+        _ => Instruction::Unknown { opcode },
+    }
+}
+
+impl Instruction {
+    // whether the instruction exists at all on the given platform
+    pub fn is_supported_on(&self, platform: Platform) -> bool {
+        match self {
+            Self::ScrollDown { .. }
+            | Self::ScrollRight
+            | Self::ScrollLeft
+            | Self::Exit
+            | Self::LowRes
+            | Self::HighRes
+            | Self::LoadBigDigit { .. }
+            | Self::StoreFlags { .. }
+            | Self::ReadFlags { .. } => platform != Platform::Chip8,
+            Self::ScrollUp { .. }
+            | Self::StoreRange { .. }
+            | Self::ReadRange { .. }
+            | Self::LoadLongIndex
+            | Self::SelectPlanes { .. }
+            | Self::LoadAudioPattern
+            | Self::SetPitch { .. } => platform == Platform::XoChip,
+            Self::Unknown { .. } => false,
+            _ => true,
+        }
+    }
+}
+
+// mnemonics in the style of Cowgod's chip-8 technical reference, with the usual SUPER-CHIP
+// and XO-CHIP additions. F000's address lives in the following word, which we can't see here.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::ClearScreen => write!(f, "CLS"),
+            Self::Return => write!(f, "RET"),
+            Self::ScrollDown { n } => write!(f, "SCD {n}"),
+            Self::ScrollUp { n } => write!(f, "SCU {n}"),
+            Self::ScrollRight => write!(f, "SCR"),
+            Self::ScrollLeft => write!(f, "SCL"),
+            Self::Exit => write!(f, "EXIT"),
+            Self::LowRes => write!(f, "LOW"),
+            Self::HighRes => write!(f, "HIGH"),
+            Self::Jump { nnn } => write!(f, "JP 0x{nnn:03X}"),
+            Self::Call { nnn } => write!(f, "CALL 0x{nnn:03X}"),
+            Self::SkipIfEqualsByte { x, nn } => write!(f, "SE V{x:X}, 0x{nn:02X}"),
+            Self::SkipIfNotEqualsByte { x, nn } => write!(f, "SNE V{x:X}, 0x{nn:02X}"),
+            Self::SkipIfEqualsRegisters { x, y } => write!(f, "SE V{x:X}, V{y:X}"),
+            Self::StoreRange { x, y } => write!(f, "SAVE V{x:X}, V{y:X}"),
+            Self::ReadRange { x, y } => write!(f, "LOAD V{x:X}, V{y:X}"),
+            Self::Load { x, nn } => write!(f, "LD V{x:X}, 0x{nn:02X}"),
+            Self::AddToRegister { x, nn } => write!(f, "ADD V{x:X}, 0x{nn:02X}"),
+            Self::LoadRegister { x, y } => write!(f, "LD V{x:X}, V{y:X}"),
+            Self::Or { x, y } => write!(f, "OR V{x:X}, V{y:X}"),
+            Self::And { x, y } => write!(f, "AND V{x:X}, V{y:X}"),
+            Self::Xor { x, y } => write!(f, "XOR V{x:X}, V{y:X}"),
+            Self::Add { x, y } => write!(f, "ADD V{x:X}, V{y:X}"),
+            Self::Sub { x, y } => write!(f, "SUB V{x:X}, V{y:X}"),
+            Self::ShiftRight { x, y } => write!(f, "SHR V{x:X}, V{y:X}"),
+            Self::SubN { x, y } => write!(f, "SUBN V{x:X}, V{y:X}"),
+            Self::ShiftLeft { x, y } => write!(f, "SHL V{x:X}, V{y:X}"),
+            Self::SkipIfNotEqualsRegisters { x, y } => write!(f, "SNE V{x:X}, V{y:X}"),
+            Self::LoadIndex { nnn } => write!(f, "LD I, 0x{nnn:03X}"),
+            Self::JumpV0 { nnn } => write!(f, "JP V0, 0x{nnn:03X}"),
+            Self::Random { x, nn } => write!(f, "RND V{x:X}, 0x{nn:02X}"),
+            Self::Draw { x, y, n } => write!(f, "DRW V{x:X}, V{y:X}, {n}"),
+            Self::SkipKey { x } => write!(f, "SKP V{x:X}"),
+            Self::SkipNotKey { x } => write!(f, "SKNP V{x:X}"),
+            Self::LoadLongIndex => write!(f, "LD I, LONG"),
+            Self::SelectPlanes { x } => write!(f, "PLANE {x}"),
+            Self::LoadAudioPattern => write!(f, "AUDIO"),
+            Self::LoadDelay { x } => write!(f, "LD V{x:X}, DT"),
+            Self::WaitKey { x } => write!(f, "LD V{x:X}, K"),
+            Self::SetDelay { x } => write!(f, "LD DT, V{x:X}"),
+            Self::SetSound { x } => write!(f, "LD ST, V{x:X}"),
+            Self::AddToIndex { x } => write!(f, "ADD I, V{x:X}"),
+            Self::LoadDigit { x } => write!(f, "LD F, V{x:X}"),
+            Self::LoadBigDigit { x } => write!(f, "LD HF, V{x:X}"),
+            Self::Bcd { x } => write!(f, "LD B, V{x:X}"),
+            Self::SetPitch { x } => write!(f, "PITCH V{x:X}"),
+            Self::StoreRegisters { x } => write!(f, "LD [I], V{x:X}"),
+            Self::ReadRegisters { x } => write!(f, "LD V{x:X}, [I]"),
+            Self::StoreFlags { x } => write!(f, "LD R, V{x:X}"),
+            Self::ReadFlags { x } => write!(f, "LD V{x:X}, R"),
+            Self::Unknown { opcode } => write!(f, "DW 0x{opcode:04X}"),
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use crate::config::{ControllerConfig, KeymapConfig};
+use sdl2::controller::Button;
+use sdl2::keyboard::{Keycode, Scancode};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+// the usual layout, mirroring the COSMAC VIP's keypad on the left of the keyboard, plus
+// the arrow keys and space for the 2/4/6/8/5 many games move and shoot with
+const DEFAULT_KEYMAP: [(usize, &[&str]); 16] = [
+    (0x1, &["1"]),
+    (0x2, &["2", "Up"]),
+    (0x3, &["3"]),
+    (0xC, &["4"]),
+    (0x4, &["Q", "Left"]),
+    (0x5, &["W", "Space"]),
+    (0x6, &["E", "Right"]),
+    (0xD, &["R"]),
+    (0x7, &["A"]),
+    (0x8, &["S", "Down"]),
+    (0x9, &["D"]),
+    (0xE, &["F"]),
+    (0xA, &["Z"]),
+    (0x0, &["X"]),
+    (0xB, &["C"]),
+    (0xF, &["V"]),
+];
+
+// scancodes name where a key physically is, keycodes what is printed on it. the default
+// layout is positional, keycodes suit remapping on non-QWERTY keyboards better.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Scancode,
+    Keycode,
+}
+
+pub struct Keymap {
+    mode: Mode,
+    scancodes: HashMap<Scancode, usize>,
+    keycodes: HashMap<Keycode, usize>,
+}
+
+impl Keymap {
+    // the defaults, with the config's keymap and then the rom's own on top
+    pub fn new(config: &KeymapConfig, rom: Option<&KeymapConfig>) -> Result<Self, String> {
+        let mode_name = rom.and_then(|rom| rom.mode.as_deref());
+        let mode = match mode_name.or(config.mode.as_deref()) {
+            None | Some("scancode") => Mode::Scancode,
+            Some("keycode") => Mode::Keycode,
+            Some(mode) => return Err(format!("Unknown keymap mode {mode}.")),
+        };
+
+        let defaults: BTreeMap<usize, Vec<String>> = DEFAULT_KEYMAP
+            .iter()
+            .map(|(key, names)| (*key, names.iter().map(|name| name.to_string()).collect()))
+            .collect();
+        let mut layers = vec![defaults];
+        for keys in [Some(&config.keys), rom.map(|rom| &rom.keys)]
+            .into_iter()
+            .flatten()
+        {
+            let mut layer = BTreeMap::new();
+            for (key, names) in keys {
+                let keypad_key = usize::from_str_radix(key, 16)
+                    .ok()
+                    .filter(|&key| key < 16)
+                    .ok_or_else(|| format!("{key} isn't a keypad key, use 0-F."))?;
+                layer.insert(keypad_key, names.clone());
+            }
+            layers.push(layer);
+        }
+
+        let mut keymap = Self {
+            mode,
+            scancodes: HashMap::new(),
+            keycodes: HashMap::new(),
+        };
+        match mode {
+            Mode::Scancode => keymap.scancodes = bind(&layers, Scancode::from_name)?,
+            Mode::Keycode => keymap.keycodes = bind(&layers, Keycode::from_name)?,
+        }
+        Ok(keymap)
+    }
+
+    pub fn keypad_key(
+        &self,
+        scancode: Option<Scancode>,
+        keycode: Option<Keycode>,
+    ) -> Option<usize> {
+        match self.mode {
+            Mode::Scancode => self.scancodes.get(&scancode?),
+            Mode::Keycode => self.keycodes.get(&keycode?),
+        }
+        .copied()
+    }
+}
+
+// each layer goes on top of the ones before it: a keypad key it mentions loses its earlier
+// host keys, and a host key it hands out is taken off whichever keypad key had it
+fn bind<T: Eq + Hash>(
+    layers: &[BTreeMap<usize, Vec<String>>],
+    from_name: impl Fn(&str) -> Option<T>,
+) -> Result<HashMap<T, usize>, String> {
+    let mut bound = HashMap::new();
+    for layer in layers {
+        let mut claimed = HashMap::new();
+        for (&keypad_key, names) in layer {
+            bound.retain(|_, key| *key != keypad_key);
+            for name in names {
+                let host_key = from_name(name).ok_or_else(|| format!("Unknown key {name}."))?;
+                match claimed.insert(host_key, keypad_key) {
+                    Some(other) if other != keypad_key => {
+                        return Err(format!(
+                            "{name} is bound to both {other:X} and {keypad_key:X}."
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        bound.extend(claimed);
+    }
+    Ok(bound)
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::new(&KeymapConfig::default(), None).expect("The default keymap is broken.")
+    }
+}
+
+// the first controller gets the same 2/4/6/8/5 layout as the arrow keys, the second one
+// the letter keys, where two player games like Pong put the other player. no key is on
+// both, so one player can't press the other's keys.
+const DEFAULT_CONTROLLERS: [&[(&str, usize)]; 2] = [
+    &[
+        ("dpup", 0x2),
+        ("dpdown", 0x8),
+        ("dpleft", 0x4),
+        ("dpright", 0x6),
+        ("a", 0x5),
+        ("b", 0x0),
+        ("x", 0x7),
+        ("y", 0x9),
+        ("leftshoulder", 0x1),
+        ("rightshoulder", 0x3),
+    ],
+    &[
+        ("dpup", 0xC),
+        ("dpdown", 0xD),
+        ("dpleft", 0xE),
+        ("dpright", 0xF),
+        ("a", 0xB),
+        ("b", 0xA),
+    ],
+];
+
+// the rom database's key hints, as the player and button they belong on
+const HINT_BUTTONS: [(&str, usize, &str); 12] = [
+    ("up", 0, "dpup"),
+    ("down", 0, "dpdown"),
+    ("left", 0, "dpleft"),
+    ("right", 0, "dpright"),
+    ("a", 0, "a"),
+    ("b", 0, "b"),
+    ("player2Up", 1, "dpup"),
+    ("player2Down", 1, "dpdown"),
+    ("player2Left", 1, "dpleft"),
+    ("player2Right", 1, "dpright"),
+    ("player2A", 1, "a"),
+    ("player2B", 1, "b"),
+];
+
+// which keypad key each button of each player's controller presses
+pub struct ControllerMap {
+    players: Vec<HashMap<Button, usize>>,
+}
+
+impl ControllerMap {
+    // the defaults, then the rom's key hints, the config's mapping and the rom's own
+    // mapping on top, one button at a time
+    pub fn new(
+        config: &[ControllerConfig],
+        rom: Option<&[ControllerConfig]>,
+        key_hints: &BTreeMap<String, u8>,
+    ) -> Result<Self, String> {
+        let button = |name: &str| {
+            Button::from_string(name).ok_or_else(|| format!("Unknown controller button {name}."))
+        };
+
+        let mut players = Vec::new();
+        for buttons in DEFAULT_CONTROLLERS {
+            let mut player = HashMap::new();
+            for (name, key) in buttons {
+                player.insert(button(name)?, *key);
+            }
+            players.push(player);
+        }
+
+        for (hint, player, name) in HINT_BUTTONS {
+            if let Some(&key) = key_hints.get(hint) {
+                let keypad_key = Some(key as usize)
+                    .filter(|&key| key < 16)
+                    .ok_or_else(|| format!("The {hint} key hint {key} isn't a keypad key."))?;
+                players[player].insert(button(name)?, keypad_key);
+            }
+        }
+
+        for controllers in [Some(config), rom].into_iter().flatten() {
+            for (player, buttons) in controllers.iter().enumerate() {
+                if players.len() <= player {
+                    players.resize_with(player + 1, HashMap::new);
+                }
+                for (name, key) in buttons {
+                    let keypad_key = usize::from_str_radix(key, 16)
+                        .ok()
+                        .filter(|&key| key < 16)
+                        .ok_or_else(|| format!("{key} isn't a keypad key, use 0-F."))?;
+                    players[player].insert(button(name)?, keypad_key);
+                }
+            }
+        }
+
+        Ok(Self { players })
+    }
+
+    pub fn keypad_key(&self, player: usize, button: Button) -> Option<usize> {
+        self.players.get(player)?.get(&button).copied()
+    }
+}
+
+impl Default for ControllerMap {
+    fn default() -> Self {
+        Self::new(&[], None, &BTreeMap::new()).expect("The default controller mapping is broken.")
+    }
+}
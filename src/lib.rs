@@ -0,0 +1,25 @@
+pub mod assembler;
+mod cartridge;
+pub mod chip8;
+pub mod database;
+pub mod debugger;
+pub mod disassembler;
+pub mod error;
+pub mod frontend;
+pub mod gdb;
+pub mod instruction;
+pub mod palette;
+pub mod platform;
+pub mod quirks;
+pub mod rewind;
+pub mod rng;
+pub mod rom;
+mod state;
+
+pub use chip8::Chip8;
+pub use error::{AssembleError, Chip8Error, StateError};
+pub use frontend::{AudioSink, Clock, Command, DisplaySink, InputSource, Runner};
+pub use instruction::{decode, Instruction};
+pub use platform::Platform;
+pub use quirks::Quirks;
+pub use rom::{Rom, RomOptions};
@@ -1,47 +1,301 @@
-mod chip8;
+mod audio;
+mod config;
+mod keymap;
 mod screen;
 
-use chip8::Chip8;
-use screen::Screen;
+use audio::{Audio, Tone, Waveform};
+use config::{Config, PaletteConfig};
+use keymap::{ControllerMap, Keymap};
+use rust_8::assembler::assemble;
+use rust_8::database::RomDatabase;
+use rust_8::debugger::Debugger;
+use rust_8::disassembler::{disassemble_rom, Syntax};
+use rust_8::frontend::{SystemClock, DEFAULT_TICKRATE};
+use rust_8::gdb::GdbStub;
+use rust_8::rewind::Rewind;
+use rust_8::rng::{Generator, VipRandom};
+use rust_8::{Chip8, Platform, Quirks, Rom, Runner};
+use screen::{Afterglow, Screen, DEFAULT_SCALE, MAX_AFTERGLOW};
 use std::env;
-use std::time::{Duration, Instant};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
 
-const IPS: u32 = 700;
-const CLOCK_RATE: u8 = 60;
-const NANOS_PER_INSTRUCTION: Duration = Duration::from_nanos(1_000_000_000 / IPS as u64);
-const NANOS_PER_CLOCK: Duration = Duration::from_nanos(1_000_000_000 / CLOCK_RATE as u64);
+// rust-8 disasm <rom> [--syntax cowgod|octo] [--follow]
+fn disasm(args: &[String]) {
+    let mut syntax = Syntax::default();
+    let mut follow = false;
+    let mut file_path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--follow" => follow = true,
+            "--syntax" => {
+                let name = args.next().expect("--syntax needs cowgod or octo.");
+                syntax =
+                    Syntax::from_name(name).unwrap_or_else(|| panic!("Unknown syntax {name}."));
+            }
+            _ => file_path = Some(arg),
+        }
+    }
 
-fn main() {
-    let mut screen = Screen::new();
-    let mut chip8 = Chip8::new();
+    let file_path =
+        file_path.expect("Usage: rust-8 disasm <rom> [--syntax cowgod|octo] [--follow]");
+    let rom = Rom::read(file_path).unwrap_or_else(|err| panic!("Can't read {file_path}: {err}"));
+    print!("{}", disassemble_rom(&rom.program, syntax, follow));
+}
 
-    let args: Vec<String> = env::args().collect();
-    let file_path = &args[1];
+// rust-8 asm <source> [--output <rom>], the rom goes next to the source by default
+fn asm(args: &[String]) {
+    let mut output = None;
+    let mut file_path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--output" | "-o" => output = Some(args.next().expect("--output needs a file name.")),
+            _ => file_path = Some(arg),
+        }
+    }
 
-    chip8
-        .load_rom(file_path)
-        .expect("File {file_path} not found.");
+    let file_path = file_path.expect("Usage: rust-8 asm <source> [--output <rom>]");
+    let source =
+        fs::read_to_string(file_path).unwrap_or_else(|err| panic!("Can't read {file_path}: {err}"));
+    let rom = match assemble(&source) {
+        Ok(rom) => rom,
+        Err(err) => {
+            eprintln!("{file_path}:{err}");
+            process::exit(1);
+        }
+    };
 
-    let mut last_instruction_time = Instant::now();
-    let mut last_clock_time = Instant::now();
+    let output = match output {
+        Some(output) => PathBuf::from(output),
+        None => Path::new(file_path).with_extension("ch8"),
+    };
+    fs::write(&output, &rom)
+        .unwrap_or_else(|err| panic!("Can't write {}: {err}", output.display()));
+    println!("Wrote {} bytes to {}", rom.len(), output.display());
+}
 
-    'running: loop {
-        if !screen.process_input(chip8.get_keypad()) {
-            break 'running;
-        }
+fn main() {
+    let all_args: Vec<String> = env::args().skip(1).collect();
+    match all_args.first().map(String::as_str) {
+        Some("disasm") => return disasm(&all_args[1..]),
+        Some("asm") => return asm(&all_args[1..]),
+        _ => {}
+    }
 
-        if last_instruction_time.elapsed() >= NANOS_PER_INSTRUCTION {
-            chip8.tick();
-            last_instruction_time += NANOS_PER_INSTRUCTION;
+    let mut debug = false;
+    let mut gdb_port = None;
+    let mut rom_db = None;
+    let mut config_path = None;
+    let mut tone = Tone::default();
+    let mut palette = PaletteConfig::default();
+    let mut scale = DEFAULT_SCALE;
+    let mut integer_scale = false;
+    let mut afterglow = None;
+    let mut display_wait = None;
+    let mut seed = None;
+    let mut generator = None;
+    let mut vip_rom = None;
+    let mut args = Vec::new();
+    let mut all_args = all_args.into_iter();
+    while let Some(arg) = all_args.next() {
+        match arg.as_str() {
+            "--debug" => debug = true,
+            "--gdb" => {
+                let port = all_args.next().and_then(|port| port.parse::<u16>().ok());
+                gdb_port = Some(port.expect("--gdb needs a port number."));
+            }
+            "--tone" => {
+                let frequency = all_args.next().and_then(|hz| hz.parse::<f32>().ok());
+                tone.frequency = frequency.expect("--tone needs a frequency in Hz.");
+            }
+            "--volume" => {
+                let volume = all_args
+                    .next()
+                    .and_then(|volume| volume.parse::<f32>().ok());
+                tone.volume = volume
+                    .expect("--volume needs a percentage.")
+                    .clamp(0.0, 100.0)
+                    / 100.0;
+            }
+            "--waveform" => {
+                let name = all_args.next().expect("--waveform needs a name.");
+                tone.waveform = Waveform::from_name(&name)
+                    .unwrap_or_else(|| panic!("Unknown waveform {name}."));
+            }
+            "--config" => {
+                config_path = Some(PathBuf::from(
+                    all_args.next().expect("--config needs a file."),
+                ))
+            }
+            "--palette" => {
+                palette.preset = Some(all_args.next().expect("--palette needs a preset."))
+            }
+            "--colors" => {
+                let colors = all_args.next().expect("--colors needs a list of colors.");
+                palette.colors = colors.split(',').map(str::to_string).collect();
+            }
+            "--scale" => {
+                let value = all_args.next().and_then(|scale| scale.parse::<u32>().ok());
+                scale = value
+                    .filter(|&scale| scale > 0)
+                    .expect("--scale needs a whole number above 0.");
+            }
+            "--integer-scale" => integer_scale = true,
+            "--phosphor" | "--blend" => {
+                let strength = all_args
+                    .next()
+                    .and_then(|strength| strength.parse::<f32>().ok())
+                    .unwrap_or_else(|| panic!("{arg} needs a percentage."))
+                    / 100.0;
+                if !(0.0..=MAX_AFTERGLOW).contains(&strength) {
+                    panic!(
+                        "{arg} needs a percentage from 0 to {}.",
+                        (MAX_AFTERGLOW * 100.0).round()
+                    );
+                }
+                afterglow = Some(match arg.as_str() {
+                    "--phosphor" => Afterglow::Phosphor(strength),
+                    _ => Afterglow::Blend(strength),
+                });
+            }
+            "--display-wait" => {
+                display_wait = match all_args.next().as_deref() {
+                    Some("on") => Some(true),
+                    Some("off") => Some(false),
+                    _ => panic!("--display-wait needs on or off."),
+                }
+            }
+            "--seed" => {
+                let value = all_args.next().and_then(|seed| seed.parse::<u64>().ok());
+                seed = Some(value.expect("--seed needs a whole number."));
+            }
+            "--rng" => {
+                let name = all_args.next().expect("--rng needs a generator.");
+                generator = Some(
+                    Generator::from_name(&name)
+                        .unwrap_or_else(|| panic!("Unknown generator {name}.")),
+                );
+            }
+            "--vip-rom" => vip_rom = Some(all_args.next().expect("--vip-rom needs a file.")),
+            "--rom-db" => rom_db = Some(all_args.next().expect("--rom-db needs a folder.")),
+            _ => args.push(arg),
         }
+    }
 
-        if last_clock_time.elapsed() >= NANOS_PER_CLOCK {
-            chip8.tick_clock();
-            last_clock_time += NANOS_PER_INSTRUCTION;
+    // only one debugger can drive the machine
+    if debug && gdb_port.is_some() {
+        panic!("--debug and --gdb can't be used together.");
+    }
+    // a dump of the interpreter is only any use to the VIP's generator
+    if vip_rom.is_some() && generator == Some(Generator::Xorshift) {
+        panic!("--vip-rom only goes with --rng vip.");
+    }
+    let file_path = &args[0];
+    let rom = Rom::read(file_path).unwrap_or_else(|err| panic!("Can't load {file_path}: {err}"));
+    let database = match rom_db {
+        Some(folder) => RomDatabase::load(Path::new(&folder))
+            .unwrap_or_else(|err| panic!("Can't load the rom database in {folder}: {err}")),
+        None => RomDatabase::bundled(),
+    };
+    // a cartridge's own options come first, then the database's
+    let options = match database.lookup(&rom.program) {
+        Some(entry) => {
+            println!("Recognized {}", entry.title);
+            rom.options.or(entry.options)
         }
+        None => rom.options,
+    };
+    if !options.key_hints.is_empty() {
+        let hints: Vec<String> = options
+            .key_hints
+            .iter()
+            .map(|(action, key)| format!("{action} {key:X}"))
+            .collect();
+        println!("Keys: {}", hints.join(", "));
+    }
+
+    // the command line wins over whatever the rom asks for, and a platform given there
+    // brings its own quirks unless they are given too
+    let platform = args.get(1).map(|name| {
+        Platform::from_name(name).unwrap_or_else(|| panic!("Unknown platform {name}."))
+    });
+    let quirks = args.get(2).map(|name| {
+        Quirks::from_name(name).unwrap_or_else(|| panic!("Unknown quirks preset {name}."))
+    });
+    let (platform, quirks) = options.machine(platform, quirks);
 
-        screen.draw(chip8.get_video_buffer());
+    // the default config file is optional, one asked for on the command line isn't
+    let config = match config_path.or_else(|| Config::default_path().filter(|path| path.exists())) {
+        Some(path) => Config::load(&path)
+            .unwrap_or_else(|err| panic!("Can't load the config in {}: {err}", path.display())),
+        None => Config::default(),
+    };
+    let rom_config = config.rom(file_path);
+    let keymap = Keymap::new(&config.keymap, rom_config.map(|rom| &rom.keymap))
+        .unwrap_or_else(|err| panic!("Bad keymap: {err}"));
+    let controller_map = ControllerMap::new(
+        &config.controllers,
+        rom_config.map(|rom| rom.controllers.as_slice()),
+        &options.key_hints,
+    )
+    .unwrap_or_else(|err| panic!("Bad controller mapping: {err}"));
 
-        std::thread::sleep(Duration::from_micros(100)); // a little cpu nap
+    let mut screen = Screen::new(scale);
+    screen.set_integer_scale(integer_scale);
+    screen.set_afterglow(afterglow);
+    screen.set_keymap(keymap);
+    screen.set_controller_map(controller_map);
+    // the command line, then the rom's settings, the rom's own colors and the config's
+    let resolve = |palette: &PaletteConfig| {
+        palette
+            .palette()
+            .unwrap_or_else(|err| panic!("Bad palette: {err}"))
+    };
+    let rom_palette = rom_config.and_then(|rom| resolve(&rom.palette));
+    let config_palette = resolve(&config.palette);
+    let palette = resolve(&palette)
+        .or(rom_palette)
+        .or(options.palette)
+        .or(config_palette)
+        .unwrap_or_default();
+    screen.set_palette(palette);
+    let mut audio = Audio::new(screen.sdl_context(), tone);
+    let quirks = Quirks {
+        display_wait: display_wait.unwrap_or(quirks.display_wait),
+        ..quirks
+    };
+    let mut chip8 = Chip8::new(platform, quirks);
+    if let Some(path) = vip_rom {
+        let rng = VipRandom::load(rand::random(), Path::new(&path))
+            .unwrap_or_else(|err| panic!("Can't load the VIP interpreter in {path}: {err}"));
+        chip8.set_random_source(Box::new(rng));
+    } else if let Some(generator) = generator {
+        chip8.set_random_source(generator.source(rand::random()));
+    }
+    if let Some(seed) = seed {
+        chip8.seed_random(seed);
+    }
+    chip8
+        .load_program(&rom.program)
+        .unwrap_or_else(|err| panic!("Can't load {file_path}: {err}"));
+
+    let mut runner = Runner::new(options.tickrate.unwrap_or(DEFAULT_TICKRATE))
+        .with_save_states(file_path)
+        .with_rewind(Rewind::default());
+    if let Some(port) = gdb_port {
+        let stub =
+            GdbStub::bind(port).unwrap_or_else(|err| panic!("Can't listen on {port}: {err}"));
+        // the rom runs until a client attaches, it doesn't wait for one
+        eprintln!("Listening for gdb on 127.0.0.1:{port}");
+        runner = runner.with_debugger(stub);
+    } else if debug {
+        runner = runner.with_debugger(Debugger::new());
+    }
+    let mut clock = SystemClock::new();
+    if let Err(err) = runner.run(&mut chip8, &mut screen, &mut audio, &mut clock) {
+        eprintln!("The rom crashed: {err}");
     }
 }
@@ -0,0 +1,116 @@
+// colors for the screen, kept free of any frontend's color type so roms and config files
+// can describe them
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    // "#RRGGBB" or the short "#RGB", with or without the #
+    pub fn from_hex(text: &str) -> Option<Self> {
+        let digits = text.strip_prefix('#').unwrap_or(text);
+        if !digits.is_ascii() {
+            return None;
+        }
+        let channel = |i: usize, width: usize| {
+            let value = u8::from_str_radix(&digits[i * width..(i + 1) * width], 16).ok()?;
+            Some(if width == 1 { value * 0x11 } else { value })
+        };
+        let width = match digits.len() {
+            3 => 1,
+            6 => 2,
+            _ => return None,
+        };
+        Some(Self::new(
+            channel(0, width)?,
+            channel(1, width)?,
+            channel(2, width)?,
+        ))
+    }
+}
+
+// indexed by the plane bits of a pixel: background, plane 1, plane 2, both planes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette(pub [Rgb; 4]);
+
+impl Palette {
+    pub const fn monochrome() -> Self {
+        Self([
+            Rgb::new(0x00, 0x00, 0x00),
+            Rgb::new(0xFF, 0xFF, 0xFF),
+            Rgb::new(0xAA, 0xAA, 0xAA),
+            Rgb::new(0x55, 0x55, 0x55),
+        ])
+    }
+
+    // the colors Octo starts out with
+    pub const fn octo() -> Self {
+        Self([
+            Rgb::new(0x99, 0x66, 0x00),
+            Rgb::new(0xFF, 0xCC, 0x00),
+            Rgb::new(0xFF, 0x66, 0x00),
+            Rgb::new(0x66, 0x22, 0x00),
+        ])
+    }
+
+    // a green phosphor tube, the second plane and blend in dimmer shades
+    pub const fn green_phosphor() -> Self {
+        Self([
+            Rgb::new(0x0A, 0x14, 0x0A),
+            Rgb::new(0x33, 0xFF, 0x66),
+            Rgb::new(0x1A, 0x99, 0x40),
+            Rgb::new(0x0F, 0x55, 0x26),
+        ])
+    }
+
+    pub const fn amber() -> Self {
+        Self([
+            Rgb::new(0x14, 0x0C, 0x00),
+            Rgb::new(0xFF, 0xB0, 0x00),
+            Rgb::new(0xB3, 0x6B, 0x00),
+            Rgb::new(0x66, 0x3D, 0x00),
+        ])
+    }
+
+    // dark pixels on a greenish handheld LCD
+    pub const fn lcd() -> Self {
+        Self([
+            Rgb::new(0x9B, 0xBC, 0x0F),
+            Rgb::new(0x0F, 0x38, 0x0F),
+            Rgb::new(0x30, 0x62, 0x30),
+            Rgb::new(0x8B, 0xAC, 0x0F),
+        ])
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "monochrome" | "default" => Some(Self::monochrome()),
+            "octo" => Some(Self::octo()),
+            "green" | "green-phosphor" => Some(Self::green_phosphor()),
+            "amber" => Some(Self::amber()),
+            "lcd" => Some(Self::lcd()),
+            _ => None,
+        }
+    }
+
+    // replaces the colors from the background on, the rest are kept
+    pub fn with_colors(mut self, colors: &[Rgb]) -> Self {
+        for (color, new) in self.0.iter_mut().zip(colors) {
+            *color = *new;
+        }
+        self
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::monochrome()
+    }
+}
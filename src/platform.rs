@@ -0,0 +1,37 @@
+use crate::quirks::Quirks;
+
+// which chip-8 dialect the rom was written for. this decides which extended opcodes
+// are available, while the quirks decide how the shared ones behave.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Platform {
+    #[default]
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl Platform {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "chip8" | "chip-8" | "vip" => Some(Self::Chip8),
+            "schip" | "superchip" | "super-chip" => Some(Self::SuperChip),
+            "xochip" | "xo-chip" | "octo" => Some(Self::XoChip),
+            _ => None,
+        }
+    }
+
+    pub fn default_quirks(&self) -> Quirks {
+        match self {
+            Self::Chip8 => Quirks::vip(),
+            Self::SuperChip => Quirks::schip(),
+            Self::XoChip => Quirks::octo(),
+        }
+    }
+
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Self::XoChip => 0x10000,
+            _ => 0x1000,
+        }
+    }
+}
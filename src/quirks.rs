@@ -0,0 +1,80 @@
+// chip-8 was reimplemented many times over the years, and each interpreter resolved the
+// ambiguous opcodes its own way. roms are written against one of them, so we let the
+// caller pick which behavior they want instead of hardcoding one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    // 8XY6/8XYE shift VX in place instead of shifting VY into VX
+    pub shift: bool,
+    // FX55/FX65 leave the index register untouched instead of advancing it
+    pub load_store: bool,
+    // BNNN behaves like BXNN, jumping to XNN + VX instead of NNN + V0
+    pub jump: bool,
+    // 8XY1/8XY2/8XY3 reset VF to 0
+    pub vf_reset: bool,
+    // sprites are clipped at the screen edges instead of wrapping around
+    pub clip: bool,
+    // DXYN waits for the next vertical blank before drawing
+    pub display_wait: bool,
+}
+
+impl Quirks {
+    // the original interpreter on the COSMAC VIP
+    pub fn vip() -> Self {
+        Self {
+            shift: false,
+            load_store: false,
+            jump: false,
+            vf_reset: true,
+            clip: true,
+            display_wait: true,
+        }
+    }
+
+    // SUPER-CHIP 1.1 on the HP48 calculators
+    pub fn schip() -> Self {
+        Self {
+            shift: true,
+            load_store: true,
+            jump: true,
+            vf_reset: false,
+            clip: true,
+            display_wait: false,
+        }
+    }
+
+    // Octo, which is also what XO-CHIP roms expect
+    pub fn octo() -> Self {
+        Self {
+            shift: false,
+            load_store: false,
+            jump: false,
+            vf_reset: false,
+            clip: false,
+            display_wait: false,
+        }
+    }
+
+    // what most chip-8 roms written today expect: Octo's, but clipping sprites at the edges
+    pub fn modern() -> Self {
+        Self {
+            clip: true,
+            ..Self::octo()
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "vip" | "chip8" | "chip-8" => Some(Self::vip()),
+            "schip" | "superchip" | "super-chip" => Some(Self::schip()),
+            "octo" | "xochip" | "xo-chip" => Some(Self::octo()),
+            "modern" => Some(Self::modern()),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::octo()
+    }
+}
@@ -0,0 +1,260 @@
+use crate::chip8::Chip8;
+use std::collections::VecDeque;
+
+// a keyframe every this many frames. deltas are taken against the keyframe, so a longer
+// interval means fewer big keyframes but deltas that drift further from their base.
+const KEYFRAME_INTERVAL: usize = 120;
+pub const DEFAULT_REWIND_BYTES: usize = 8 * 1024 * 1024;
+
+// a keyframe and the frames recorded after it, all stored as compressed save states
+struct Group {
+    length: usize,
+    keyframe: Vec<u8>,
+    deltas: Vec<Vec<u8>>,
+}
+
+impl Group {
+    fn bytes(&self) -> usize {
+        self.keyframe.len() + self.deltas.iter().map(Vec::len).sum::<usize>()
+    }
+}
+
+// remembers the last few minutes of play so they can be stepped back through frame by frame
+pub struct Rewind {
+    groups: VecDeque<Group>,
+    // the newest keyframe uncompressed, every delta recorded now is taken against it
+    latest_keyframe: Vec<u8>,
+    max_bytes: usize,
+    bytes: usize,
+}
+
+impl Rewind {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            groups: VecDeque::new(),
+            latest_keyframe: Vec::new(),
+            max_bytes,
+            bytes: 0,
+        }
+    }
+
+    // `state` comes from Chip8::save_state
+    pub fn record(&mut self, state: Vec<u8>) {
+        let start_group = match self.groups.back() {
+            // a resolution change alters the state size, and deltas need equal sizes
+            Some(group) => {
+                group.deltas.len() + 1 >= KEYFRAME_INTERVAL || group.length != state.len()
+            }
+            None => true,
+        };
+
+        if start_group {
+            let keyframe = encode(&vec![0; state.len()], &state);
+            self.bytes += keyframe.len();
+            self.groups.push_back(Group {
+                length: state.len(),
+                keyframe,
+                deltas: Vec::new(),
+            });
+            self.latest_keyframe = state;
+        } else {
+            let delta = encode(&self.latest_keyframe, &state);
+            self.bytes += delta.len();
+            self.groups.back_mut().unwrap().deltas.push(delta);
+        }
+
+        // drop whole groups from the oldest end, the newest one always stays
+        while self.bytes > self.max_bytes && self.groups.len() > 1 {
+            let group = self.groups.pop_front().unwrap();
+            self.bytes -= group.bytes();
+        }
+    }
+
+    // restores the most recently recorded frame and forgets it. returns false once there
+    // is no history left
+    pub fn step_back(&mut self, chip8: &mut Chip8) -> bool {
+        let Some(group) = self.groups.back_mut() else {
+            return false;
+        };
+
+        let state = match group.deltas.pop() {
+            Some(delta) => {
+                self.bytes -= delta.len();
+                decode(&self.latest_keyframe, &delta)
+            }
+            None => {
+                let group = self.groups.pop_back().unwrap();
+                self.bytes -= group.keyframe.len();
+                let state = std::mem::take(&mut self.latest_keyframe);
+                if let Some(previous) = self.groups.back() {
+                    self.latest_keyframe = decode(&vec![0; previous.length], &previous.keyframe);
+                }
+                state
+            }
+        };
+
+        // these states came from save_state, so loading them can't fail
+        chip8
+            .load_state(&state)
+            .expect("rewind buffer holds a broken state");
+        true
+    }
+}
+
+impl Default for Rewind {
+    fn default() -> Self {
+        Self::new(DEFAULT_REWIND_BYTES)
+    }
+}
+
+// frame to frame most of the machine stays the same, so we xor against the base and store
+// the result as runs: [zero count][literal count][literal bytes], counts as LEB128 varints
+fn encode(base: &[u8], state: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < state.len() {
+        let zeros_start = i;
+        while i < state.len() && base[i] == state[i] {
+            i += 1;
+        }
+        let literal_start = i;
+        while i < state.len() && base[i] != state[i] {
+            i += 1;
+        }
+        write_varint(&mut out, literal_start - zeros_start);
+        write_varint(&mut out, i - literal_start);
+        out.extend((literal_start..i).map(|j| base[j] ^ state[j]));
+    }
+    out
+}
+
+fn decode(base: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut state = base.to_vec();
+    let mut position = 0;
+    let mut i = 0;
+    while i < delta.len() {
+        position += read_varint(delta, &mut i);
+        let literals = read_varint(delta, &mut i);
+        for byte in &delta[i..i + literals] {
+            state[position] ^= byte;
+            position += 1;
+        }
+        i += literals;
+    }
+    state
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], i: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*i];
+        *i += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::Instruction;
+    use crate::{Platform, Quirks};
+
+    // counts up in V0 forever, so every frame's state differs from the last
+    fn machine() -> Chip8 {
+        let mut chip8 = Chip8::new(Platform::SuperChip, Quirks::schip());
+        chip8.load_program(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        chip8
+    }
+
+    // records `frames` frames of `chip8`, returning the states recorded in order
+    fn play(rewind: &mut Rewind, chip8: &mut Chip8, frames: usize) -> Vec<Vec<u8>> {
+        let mut states = Vec::new();
+        for _ in 0..frames {
+            let state = chip8.save_state();
+            rewind.record(state.clone());
+            states.push(state);
+            chip8.tick().unwrap();
+            chip8.tick().unwrap();
+            chip8.tick_clock();
+        }
+        states
+    }
+
+    // steps back as far as the history goes, returning the states restored
+    fn rewind_all(rewind: &mut Rewind, chip8: &mut Chip8) -> Vec<Vec<u8>> {
+        let mut states = Vec::new();
+        while rewind.step_back(chip8) {
+            states.push(chip8.save_state());
+        }
+        states.reverse();
+        states
+    }
+
+    #[test]
+    fn deltas_round_trip() {
+        let long_run: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+        let cases: [(&[u8], &[u8]); 5] = [
+            (&[], &[]),
+            (&[1, 2, 3], &[1, 2, 3]),
+            (&[1, 2, 3], &[4, 5, 6]),
+            (&[0, 0, 0, 0, 0, 0], &[0, 7, 0, 0, 8, 8]),
+            (&[0; 1000], &long_run),
+        ];
+        for (base, state) in cases {
+            assert_eq!(decode(base, &encode(base, state)), state);
+        }
+        // unchanged stretches longer than a one byte count
+        let mut changed = vec![0; 1000];
+        changed[999] = 1;
+        assert_eq!(decode(&[0; 1000], &encode(&[0; 1000], &changed)), changed);
+    }
+
+    #[test]
+    fn steps_back_across_keyframes() {
+        let mut rewind = Rewind::default();
+        let mut chip8 = machine();
+        let recorded = play(&mut rewind, &mut chip8, 2 * KEYFRAME_INTERVAL + 5);
+        assert_eq!(rewind.groups.len(), 3);
+        assert_eq!(rewind_all(&mut rewind, &mut chip8), recorded);
+        assert_eq!(rewind.bytes, 0);
+    }
+
+    #[test]
+    fn resolution_changes_start_a_new_keyframe() {
+        let mut rewind = Rewind::default();
+        let mut chip8 = machine();
+        let mut recorded = play(&mut rewind, &mut chip8, 10);
+        chip8.execute(Instruction::HighRes).unwrap();
+        recorded.extend(play(&mut rewind, &mut chip8, 10));
+        chip8.execute(Instruction::LowRes).unwrap();
+        recorded.extend(play(&mut rewind, &mut chip8, 10));
+
+        assert_eq!(rewind.groups.len(), 3);
+        assert_eq!(rewind_all(&mut rewind, &mut chip8), recorded);
+    }
+
+    #[test]
+    fn the_oldest_frames_go_first() {
+        // too small for anything, but the newest group always stays
+        let mut rewind = Rewind::new(1);
+        let mut chip8 = machine();
+        let recorded = play(&mut rewind, &mut chip8, 3 * KEYFRAME_INTERVAL + 5);
+        assert_eq!(rewind.groups.len(), 1);
+
+        let kept = rewind_all(&mut rewind, &mut chip8);
+        assert_eq!(kept, recorded[3 * KEYFRAME_INTERVAL..]);
+    }
+}
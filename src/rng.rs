@@ -0,0 +1,222 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// where CXNN gets its random bytes from. the whole state has to fit in a u64 so it can go
+// into save states, and setting the same state again has to replay the same bytes.
+pub trait RandomSource: Send {
+    fn next_byte(&mut self) -> u8;
+    fn state(&self) -> u64;
+    fn set_state(&mut self, state: u64);
+}
+
+// xorshift64*, the default. ThreadRng keeps its state to itself, while this one is a single
+// number we can put in save states.
+#[derive(Debug, Clone)]
+pub struct Xorshift {
+    state: u64,
+}
+
+impl Xorshift {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Self { state: 0 };
+        rng.set_state(seed);
+        rng
+    }
+}
+
+impl RandomSource for Xorshift {
+    fn state(&self) -> u64 {
+        self.state
+    }
+
+    fn set_state(&mut self, state: u64) {
+        // an all zero state would only ever produce zeroes
+        self.state = if state == 0 {
+            0x9E3779B97F4A7C15
+        } else {
+            state
+        };
+    }
+
+    fn next_byte(&mut self) -> u8 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545F4914F6CDD1D) >> 56) as u8
+    }
+}
+
+// the COSMAC VIP interpreter's CXNN routine. its whole state is register R9: every call
+// steps the low byte, reads the byte of the interpreter's second page it now points at and
+// adds that into the high byte, which is the number handed to the rom. the page is code,
+// so it never changes and doesn't need saving.
+//
+// we don't ship the interpreter, so unless a dump of it is loaded the page is a fixed
+// stand-in. the numbers then follow the VIP's pattern, the low byte cycling every 256 calls
+// under a running sum, but not its exact values.
+#[derive(Debug, Clone)]
+pub struct VipRandom {
+    r9: u16,
+    page: [u8; 256],
+}
+
+// the stand-in for the interpreter's 0x100-0x1FF, any fixed bytes will do
+const STAND_IN_PAGE: [u8; 256] = {
+    let mut page = [0; 256];
+    let mut state: u32 = 0x1802;
+    let mut i = 0;
+    while i < page.len() {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        page[i] = (state >> 24) as u8;
+        i += 1;
+    }
+    page
+};
+
+impl VipRandom {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Self {
+            r9: 0,
+            page: STAND_IN_PAGE,
+        };
+        rng.set_state(seed);
+        rng
+    }
+
+    // `interpreter` is the VIP's chip-8 interpreter as it sits at 0x000-0x1FF
+    pub fn with_interpreter(seed: u64, interpreter: &[u8; 512]) -> Self {
+        let mut rng = Self::new(seed);
+        rng.page.copy_from_slice(&interpreter[0x100..]);
+        rng
+    }
+
+    // `path` is a dump of the interpreter, the 512 bytes from 0x000 to 0x1FF
+    pub fn load(seed: u64, path: &Path) -> io::Result<Self> {
+        let interpreter: [u8; 512] = fs::read(path)?.try_into().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the VIP's chip-8 interpreter is 512 bytes long",
+            )
+        })?;
+        Ok(Self::with_interpreter(seed, &interpreter))
+    }
+}
+
+impl RandomSource for VipRandom {
+    fn state(&self) -> u64 {
+        self.r9 as u64
+    }
+
+    // R9 is only 16 bits, seeds that differ above that give the same numbers
+    fn set_state(&mut self, state: u64) {
+        self.r9 = state as u16;
+    }
+
+    fn next_byte(&mut self) -> u8 {
+        let [mut high, low] = self.r9.to_be_bytes();
+        let low = low.wrapping_add(1);
+        high = high.wrapping_add(self.page[low as usize]);
+        self.r9 = u16::from_be_bytes([high, low]);
+        high
+    }
+}
+
+// the generators the frontends can pick with --rng
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Generator {
+    #[default]
+    Xorshift,
+    Vip,
+}
+
+impl Generator {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "xorshift" => Some(Self::Xorshift),
+            "vip" | "cosmac" => Some(Self::Vip),
+            _ => None,
+        }
+    }
+
+    pub fn source(&self, seed: u64) -> Box<dyn RandomSource> {
+        match self {
+            Self::Xorshift => Box::new(Xorshift::new(seed)),
+            Self::Vip => Box::new(VipRandom::new(seed)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Chip8, Platform, Quirks};
+
+    // C0FF then a jump back to it, so every tick draws a number into V0
+    const DRAW_FOREVER: [u8; 4] = [0xC0, 0xFF, 0x12, 0x00];
+
+    fn draws(chip8: &mut Chip8, count: usize) -> Vec<u8> {
+        (0..count)
+            .map(|_| {
+                chip8.tick().unwrap();
+                chip8.tick().unwrap();
+                chip8.get_registers()[0]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn vip_numbers_follow_r9() {
+        let mut rng = VipRandom::new(0x1234);
+        let first = rng.next_byte();
+        assert_eq!(first, 0x12u8.wrapping_add(STAND_IN_PAGE[0x35]));
+        assert_eq!(rng.state(), u16::from_be_bytes([first, 0x35]) as u64);
+
+        // the low byte wraps around without carrying into the high one
+        rng.set_state(0x00FF);
+        assert_eq!(rng.next_byte(), STAND_IN_PAGE[0]);
+        assert_eq!(rng.state() & 0xFF, 0);
+    }
+
+    #[test]
+    fn vip_numbers_come_from_the_interpreter_page() {
+        let mut interpreter = [0; 512];
+        interpreter[0x101] = 7;
+        interpreter[0x102] = 9;
+        let mut rng = VipRandom::with_interpreter(0, &interpreter);
+        assert_eq!(rng.next_byte(), 7);
+        assert_eq!(rng.next_byte(), 16);
+        assert_eq!(rng.next_byte(), 16);
+    }
+
+    #[test]
+    fn interpreter_dumps_are_read_from_files() {
+        let path = std::env::temp_dir().join(format!("rust-8-vip-{}.bin", std::process::id()));
+        let mut interpreter = [0; 512];
+        interpreter[0x101] = 7;
+        fs::write(&path, interpreter).unwrap();
+        assert_eq!(VipRandom::load(0, &path).unwrap().next_byte(), 7);
+
+        fs::write(&path, [0; 100]).unwrap();
+        let err = VipRandom::load(0, &path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn save_states_restore_the_generator() {
+        for generator in [Generator::Xorshift, Generator::Vip] {
+            let mut chip8 = Chip8::new(Platform::Chip8, Quirks::vip());
+            chip8.set_random_source(generator.source(42));
+            chip8.load_program(&DRAW_FOREVER).unwrap();
+            draws(&mut chip8, 10);
+
+            let state = chip8.save_state();
+            let expected = draws(&mut chip8, 300);
+            chip8.load_state(&state).unwrap();
+            assert_eq!(draws(&mut chip8, 300), expected, "{generator:?}");
+        }
+    }
+}
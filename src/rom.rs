@@ -0,0 +1,117 @@
+use crate::cartridge;
+use crate::palette::Palette;
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+
+// how a rom would like to be run, when the file says so. anything left as None is up to
+// the user or the defaults.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RomOptions {
+    pub platform: Option<Platform>,
+    pub tickrate: Option<u32>,
+    pub quirks: Option<Quirks>,
+    pub palette: Option<Palette>,
+    // what the rom uses its keys for, e.g. "up" -> 5
+    pub key_hints: BTreeMap<String, u8>,
+}
+
+impl RomOptions {
+    // fills in whatever these options leave open from `other`
+    pub fn or(self, other: RomOptions) -> RomOptions {
+        RomOptions {
+            platform: self.platform.or(other.platform),
+            tickrate: self.tickrate.or(other.tickrate),
+            quirks: self.quirks.or(other.quirks),
+            palette: self.palette.or(other.palette),
+            key_hints: match self.key_hints.is_empty() {
+                true => other.key_hints,
+                false => self.key_hints,
+            },
+        }
+    }
+
+    // the platform and quirks to run with, given the ones asked for on the command line. a
+    // platform picked there brings its own quirks unless they're picked too, the rom's
+    // quirks only go with the rom's platform.
+    pub fn machine(
+        &self,
+        platform: Option<Platform>,
+        quirks: Option<Quirks>,
+    ) -> (Platform, Quirks) {
+        let quirks = match (quirks, platform, self.quirks, self.platform) {
+            (Some(quirks), ..) => quirks,
+            (None, Some(platform), ..) => platform.default_quirks(),
+            (None, None, Some(quirks), _) => quirks,
+            (None, None, None, Some(platform)) => platform.default_quirks(),
+            (None, None, None, None) => Quirks::default(),
+        };
+        (platform.or(self.platform).unwrap_or_default(), quirks)
+    }
+}
+
+// a rom file as it gets loaded: the program bytes that go at 0x200, plus any options
+// that came with them
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Rom {
+    pub program: Vec<u8>,
+    pub options: RomOptions,
+}
+
+impl Rom {
+    // plain binaries are taken as they are, Octo cartridge gifs get unpacked and assembled
+    pub fn read(file_name: &str) -> io::Result<Self> {
+        let bytes = fs::read(file_name)?;
+        if cartridge::is_cartridge(&bytes) {
+            return cartridge::decode(&bytes);
+        }
+        Ok(Self {
+            program: bytes,
+            options: RomOptions::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cartridge() -> RomOptions {
+        RomOptions {
+            platform: Some(Platform::XoChip),
+            quirks: Some(Quirks::octo()),
+            ..RomOptions::default()
+        }
+    }
+
+    #[test]
+    fn a_platform_from_the_command_line_brings_its_own_quirks() {
+        let machine = cartridge().machine(Some(Platform::SuperChip), None);
+        assert_eq!(machine, (Platform::SuperChip, Quirks::schip()));
+        let machine = cartridge().machine(Some(Platform::SuperChip), Some(Quirks::vip()));
+        assert_eq!(machine, (Platform::SuperChip, Quirks::vip()));
+    }
+
+    #[test]
+    fn the_rom_decides_what_the_command_line_leaves_open() {
+        assert_eq!(
+            cartridge().machine(None, None),
+            (Platform::XoChip, Quirks::octo())
+        );
+        let machine = cartridge().machine(None, Some(Quirks::schip()));
+        assert_eq!(machine, (Platform::XoChip, Quirks::schip()));
+
+        let platform_only = RomOptions {
+            platform: Some(Platform::Chip8),
+            ..RomOptions::default()
+        };
+        assert_eq!(
+            platform_only.machine(None, None),
+            (Platform::Chip8, Quirks::vip())
+        );
+        let nothing = RomOptions::default().machine(None, None);
+        assert_eq!(nothing, (Platform::default(), Quirks::default()));
+    }
+}
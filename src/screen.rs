@@ -1,30 +1,73 @@
 extern crate sdl2;
 
+use crate::keymap::{ControllerMap, Keymap};
+use rust_8::palette::Palette;
+use rust_8::{Command, DisplaySink, InputSource};
+use sdl2::controller::GameController;
 use sdl2::event::Event;
-use sdl2::keyboard::Scancode;
+use sdl2::keyboard::{Mod, Scancode};
 use sdl2::pixels::Color;
 use sdl2::rect::Rect;
 use sdl2::render::Canvas;
-use sdl2::video::Window;
-use sdl2::EventPump;
+use sdl2::video::{FullscreenType, Window};
+use sdl2::{EventPump, GameControllerSubsystem};
+
+// the window opens at this many window pixels per low resolution pixel
+pub const DEFAULT_SCALE: u32 = 10;
+// the low resolution screen the window is sized for, high resolution just gets smaller pixels
+const LORES_WIDTH: u32 = 64;
+const LORES_HEIGHT: u32 = 32;
+pub const MAX_AFTERGLOW: f32 = 0.95;
+
+// sprites are erased and drawn again every frame, which flickers. both of these keep some
+// of the previous frames on screen, `strength` is how much of the last frame is kept (0-1).
+// keeping all of it would never show anything new, so it stops at MAX_AFTERGLOW.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Afterglow {
+    // pixels light up at once and fade out slowly, like a phosphor tube
+    Phosphor(f32),
+    // every change is spread over a few frames, turning on as well as off
+    Blend(f32),
+}
 
 pub struct Screen {
     sdl_context: sdl2::Sdl,
     canvas: Canvas<Window>,
     event_pump: EventPump,
+    // only whole window pixels per screen pixel, leaving a wider border
+    integer_scale: bool,
+    afterglow: Option<Afterglow>,
+    // the color each pixel was last drawn in, for the afterglow
+    shown: Vec<[f32; 3]>,
+    // indexed by the plane bits of a pixel
+    palette: [Color; 4],
+    keymap: Keymap,
+    // how many host keys and controller buttons are holding down each keypad key
+    held: [u8; 16],
+    controller_subsystem: GameControllerSubsystem,
+    controller_map: ControllerMap,
+    // indexed by player, a slot frees up when its controller is unplugged
+    controllers: Vec<Option<GameController>>,
+    // the controller instance and keypad key of every button held down
+    pressed_buttons: Vec<(u32, usize)>,
 }
 
 impl Screen {
-    pub fn new() -> Self {
+    pub fn new(scale: u32) -> Self {
         let sdl_context = sdl2::init().unwrap();
         let video_subsystem = sdl_context.video().unwrap();
         let event_pump = sdl_context.event_pump().unwrap();
+        // controllers already plugged in show up as added events on the first poll
+        let controller_subsystem = sdl_context.game_controller().unwrap();
 
-        let window = video_subsystem
-            .window("Rust-8", 640, 320)
+        let mut window = video_subsystem
+            .window("Rust-8", LORES_WIDTH * scale, LORES_HEIGHT * scale)
             .position_centered()
+            .resizable()
             .build()
             .unwrap();
+        // one window pixel per screen pixel at the very least
+        let _ = window.set_minimum_size(LORES_WIDTH, LORES_HEIGHT);
 
         let canvas = window.into_canvas().present_vsync().build().unwrap();
 
@@ -32,231 +75,318 @@ impl Screen {
             sdl_context,
             canvas,
             event_pump,
+            integer_scale: false,
+            afterglow: None,
+            shown: Vec::new(),
+            palette: to_colors(Palette::default()),
+            keymap: Keymap::default(),
+            held: [0; 16],
+            controller_subsystem,
+            controller_map: ControllerMap::default(),
+            controllers: Vec::new(),
+            pressed_buttons: Vec::new(),
+        }
+    }
+
+    pub fn sdl_context(&self) -> &sdl2::Sdl {
+        &self.sdl_context
+    }
+
+    pub fn set_keymap(&mut self, keymap: Keymap) {
+        self.keymap = keymap;
+        self.held = [0; 16];
+    }
+
+    pub fn set_controller_map(&mut self, controller_map: ControllerMap) {
+        self.controller_map = controller_map;
+    }
+
+    pub fn set_integer_scale(&mut self, integer_scale: bool) {
+        self.integer_scale = integer_scale;
+    }
+
+    pub fn set_afterglow(&mut self, afterglow: Option<Afterglow>) {
+        self.afterglow = afterglow;
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = to_colors(palette);
+    }
+}
+
+impl Screen {
+    fn press(&mut self, keys: &mut [bool; 16], key: usize) {
+        self.held[key] += 1;
+        keys[key] = true;
+    }
+
+    // a keypad key stays down until every host key and button bound to it is let go
+    fn release(&mut self, keys: &mut [bool; 16], key: usize) {
+        self.held[key] = self.held[key].saturating_sub(1);
+        keys[key] = self.held[key] > 0;
+    }
+
+    // the color of every pixel this frame, moving each one towards its palette color
+    fn pixel_colors(&mut self, buffer: &[u8]) -> Vec<Color> {
+        let target = |pixel: u8| self.palette[(pixel & 0x3) as usize];
+        let Some(afterglow) = self.afterglow else {
+            return buffer.iter().map(|&pixel| target(pixel)).collect();
+        };
+
+        // nothing to fade from after a resolution change
+        if self.shown.len() != buffer.len() {
+            self.shown = buffer.iter().map(|&pixel| to_rgb(target(pixel))).collect();
+        }
+        let mut colors = Vec::with_capacity(buffer.len());
+        for (shown, &pixel) in self.shown.iter_mut().zip(buffer) {
+            let strength = match afterglow {
+                Afterglow::Phosphor(_) if pixel != 0 => 0.0,
+                Afterglow::Phosphor(strength) | Afterglow::Blend(strength) => {
+                    strength.clamp(0.0, MAX_AFTERGLOW)
+                }
+            };
+            let target = to_rgb(target(pixel));
+            for (channel, target) in shown.iter_mut().zip(target) {
+                *channel = *channel * strength + target * (1.0 - strength);
+            }
+            let [r, g, b] = shown.map(|channel| channel.round() as u8);
+            colors.push(Color::RGB(r, g, b));
+        }
+        colors
+    }
+
+    fn toggle_fullscreen(&mut self) {
+        let window = self.canvas.window_mut();
+        let fullscreen = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+        if let Err(err) = window.set_fullscreen(fullscreen) {
+            eprintln!("Couldn't toggle fullscreen: {err}");
+        }
+    }
+
+    // where a width x height screen goes in the window: as big as fits while keeping its
+    // aspect ratio, centered, with black bars filling the rest
+    fn layout(&self, width: usize, height: usize) -> (f32, i32, i32) {
+        let (window_width, window_height) = self
+            .canvas
+            .output_size()
+            .unwrap_or((LORES_WIDTH * DEFAULT_SCALE, LORES_HEIGHT * DEFAULT_SCALE));
+        let mut scale =
+            (window_width as f32 / width as f32).min(window_height as f32 / height as f32);
+        if self.integer_scale {
+            scale = scale.floor().max(1.0);
+        }
+        let x = (window_width as f32 - width as f32 * scale) / 2.0;
+        let y = (window_height as f32 - height as f32 * scale) / 2.0;
+        (scale, x.round() as i32, y.round() as i32)
+    }
+
+    // takes the first free player slot
+    fn add_controller(&mut self, joystick_index: u32) {
+        let controller = match self.controller_subsystem.open(joystick_index) {
+            Ok(controller) => controller,
+            Err(err) => {
+                eprintln!("Couldn't open controller {joystick_index}: {err}");
+                return;
+            }
+        };
+        // SDL can report a controller that's already open again
+        let instance = controller.instance_id();
+        if self.player(instance).is_some() {
+            return;
+        }
+
+        let name = controller.name();
+        let slot = self.controllers.iter().position(Option::is_none);
+        let player = match slot {
+            Some(player) => {
+                self.controllers[player] = Some(controller);
+                player
+            }
+            None => {
+                self.controllers.push(Some(controller));
+                self.controllers.len() - 1
+            }
+        };
+        println!("{name} connected as player {}", player + 1);
+    }
+
+    // lets go of everything the controller was holding
+    fn remove_controller(&mut self, instance: u32, keys: &mut [bool; 16]) {
+        let Some(player) = self.player(instance) else {
+            return;
+        };
+        self.controllers[player] = None;
+        println!("Player {} disconnected", player + 1);
+
+        let (released, pressed) = self
+            .pressed_buttons
+            .drain(..)
+            .partition(|(pressed_instance, _)| *pressed_instance == instance);
+        self.pressed_buttons = pressed;
+        for (_, key) in released {
+            self.release(keys, key);
         }
     }
 
-    pub fn draw(&mut self, buffer: &[u32; 64 * 32]) {
+    fn player(&self, instance: u32) -> Option<usize> {
+        self.controllers.iter().position(|controller| {
+            controller
+                .as_ref()
+                .is_some_and(|controller| controller.instance_id() == instance)
+        })
+    }
+}
+
+fn to_colors(palette: Palette) -> [Color; 4] {
+    palette.0.map(|rgb| Color::RGB(rgb.r, rgb.g, rgb.b))
+}
+
+fn to_rgb(color: Color) -> [f32; 3] {
+    [color.r as f32, color.g as f32, color.b as f32]
+}
+
+impl DisplaySink for Screen {
+    fn draw(&mut self, buffer: &[u8], width: usize, height: usize) {
         self.canvas.set_draw_color(Color::BLACK);
         self.canvas.clear();
 
-        for (i, pixel) in buffer.iter().enumerate() {
-            if *pixel != 0 {
-                let x = (i % 64) as i32 * 10;
-                let y = (i / 64) as i32 * 10;
-                self.canvas.set_draw_color(Color::WHITE);
-                let _ = self.canvas.fill_rect(Rect::new(x, y, 10, 10));
+        let (scale, left, top) = self.layout(width, height);
+        // pixel edges are rounded separately so fractional scales don't leave gaps
+        let edge = |i: usize| (i as f32 * scale).round() as i32;
+        self.canvas.set_draw_color(self.palette[0]);
+        let _ = self.canvas.fill_rect(Rect::new(
+            left,
+            top,
+            edge(width) as u32,
+            edge(height) as u32,
+        ));
+        for (i, color) in self.pixel_colors(buffer).into_iter().enumerate() {
+            if color != self.palette[0] {
+                let (column, row) = (i % width, i / width);
+                let x = left + edge(column);
+                let y = top + edge(row);
+                let pixel_width = (edge(column + 1) - edge(column)) as u32;
+                let pixel_height = (edge(row + 1) - edge(row)) as u32;
+                self.canvas.set_draw_color(color);
+                let _ = self
+                    .canvas
+                    .fill_rect(Rect::new(x, y, pixel_width, pixel_height));
             }
         }
 
         self.canvas.present();
     }
+}
 
-    pub fn process_input(&mut self, keys: &mut [bool; 16]) -> bool {
-        for event in self.event_pump.poll_iter() {
+impl InputSource for Screen {
+    fn process_input(&mut self, keys: &mut [bool; 16]) -> Vec<Command> {
+        let mut commands = Vec::new();
+        let events: Vec<Event> = self.event_pump.poll_iter().collect();
+        for event in events {
             match event {
                 Event::Quit { .. }
                 | Event::KeyDown {
                     scancode: Some(Scancode::Escape),
                     ..
                 } => {
-                    return false; // quit
-                }
-                Event::KeyDown {
-                    scancode: Some(Scancode::Num1),
-                    ..
-                } => {
-                    keys[1] = true;
-                }
-                Event::KeyDown {
-                    scancode: Some(Scancode::Num2),
-                    ..
-                } => {
-                    keys[2] = true;
-                }
-                Event::KeyDown {
-                    scancode: Some(Scancode::Num3),
-                    ..
-                } => {
-                    keys[3] = true;
-                }
-                Event::KeyDown {
-                    scancode: Some(Scancode::Num4),
-                    ..
-                } => {
-                    keys[12] = true;
-                }
-                Event::KeyDown {
-                    scancode: Some(Scancode::Q),
-                    ..
-                } => {
-                    keys[4] = true;
-                }
-                Event::KeyDown {
-                    scancode: Some(Scancode::W),
-                    ..
-                } => {
-                    keys[5] = true;
-                }
-                Event::KeyDown {
-                    scancode: Some(Scancode::E),
-                    ..
-                } => {
-                    keys[6] = true;
-                }
-                Event::KeyDown {
-                    scancode: Some(Scancode::R),
-                    ..
-                } => {
-                    keys[13] = true;
+                    return vec![Command::Quit];
                 }
+                // holding backspace steps back in time
                 Event::KeyDown {
-                    scancode: Some(Scancode::A),
+                    scancode: Some(Scancode::Backspace),
+                    repeat: false,
                     ..
                 } => {
-                    keys[7] = true;
+                    commands.push(Command::Rewind(true));
                 }
-                Event::KeyDown {
-                    scancode: Some(Scancode::S),
-                    ..
-                } => {
-                    keys[8] = true;
-                }
-                Event::KeyDown {
-                    scancode: Some(Scancode::D),
-                    ..
-                } => {
-                    keys[9] = true;
-                }
-                Event::KeyDown {
-                    scancode: Some(Scancode::F),
-                    ..
-                } => {
-                    keys[14] = true;
-                }
-                Event::KeyDown {
-                    scancode: Some(Scancode::Z),
+                Event::KeyUp {
+                    scancode: Some(Scancode::Backspace),
                     ..
                 } => {
-                    keys[10] = true;
+                    commands.push(Command::Rewind(false));
                 }
                 Event::KeyDown {
-                    scancode: Some(Scancode::X),
+                    scancode: Some(Scancode::F11),
+                    repeat: false,
                     ..
-                } => {
-                    keys[0] = true;
-                }
+                } => self.toggle_fullscreen(),
+                // F1-F9 load the matching save state slot, holding shift saves to it instead
                 Event::KeyDown {
-                    scancode: Some(Scancode::C),
-                    ..
-                } => {
-                    keys[11] = true;
+                    scancode: Some(scancode),
+                    keymod,
+                    repeat: false,
+                    ..
+                } if save_state_slot(scancode).is_some() => {
+                    let slot = save_state_slot(scancode).unwrap();
+                    if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                        commands.push(Command::SaveState(slot));
+                    } else {
+                        commands.push(Command::LoadState(slot));
+                    }
                 }
                 Event::KeyDown {
-                    scancode: Some(Scancode::V),
-                    ..
-                } => {
-                    keys[15] = true;
-                }
-                Event::KeyUp {
-                    scancode: Some(Scancode::Num1),
-                    ..
-                } => {
-                    keys[1] = false;
-                }
-                Event::KeyUp {
-                    scancode: Some(Scancode::Num2),
-                    ..
-                } => {
-                    keys[2] = false;
-                }
-                Event::KeyUp {
-                    scancode: Some(Scancode::Num3),
-                    ..
-                } => {
-                    keys[3] = false;
-                }
-                Event::KeyUp {
-                    scancode: Some(Scancode::Num4),
-                    ..
-                } => {
-                    keys[12] = false;
-                }
-                Event::KeyUp {
-                    scancode: Some(Scancode::Q),
-                    ..
-                } => {
-                    keys[4] = false;
-                }
-                Event::KeyUp {
-                    scancode: Some(Scancode::W),
+                    scancode,
+                    keycode,
+                    repeat: false,
                     ..
                 } => {
-                    keys[5] = false;
+                    if let Some(key) = self.keymap.keypad_key(scancode, keycode) {
+                        self.press(keys, key);
+                    }
                 }
                 Event::KeyUp {
-                    scancode: Some(Scancode::E),
-                    ..
-                } => {
-                    keys[6] = false;
-                }
-                Event::KeyUp {
-                    scancode: Some(Scancode::R),
-                    ..
-                } => {
-                    keys[13] = false;
-                }
-                Event::KeyUp {
-                    scancode: Some(Scancode::A),
-                    ..
-                } => {
-                    keys[7] = false;
-                }
-                Event::KeyUp {
-                    scancode: Some(Scancode::S),
-                    ..
-                } => {
-                    keys[8] = false;
-                }
-                Event::KeyUp {
-                    scancode: Some(Scancode::D),
-                    ..
-                } => {
-                    keys[9] = false;
-                }
-                Event::KeyUp {
-                    scancode: Some(Scancode::F),
-                    ..
-                } => {
-                    keys[14] = false;
-                }
-                Event::KeyUp {
-                    scancode: Some(Scancode::Z),
-                    ..
-                } => {
-                    keys[10] = false;
+                    scancode, keycode, ..
+                } => {
+                    if let Some(key) = self.keymap.keypad_key(scancode, keycode) {
+                        self.release(keys, key);
+                    }
+                }
+                Event::ControllerDeviceAdded { which, .. } => self.add_controller(which),
+                Event::ControllerDeviceRemoved { which, .. } => self.remove_controller(which, keys),
+                Event::ControllerButtonDown { which, button, .. } => {
+                    let key = self
+                        .player(which)
+                        .and_then(|player| self.controller_map.keypad_key(player, button));
+                    if let Some(key) = key {
+                        self.pressed_buttons.push((which, key));
+                        self.press(keys, key);
+                    }
+                }
+                Event::ControllerButtonUp { which, button, .. } => {
+                    let key = self
+                        .player(which)
+                        .and_then(|player| self.controller_map.keypad_key(player, button));
+                    let pressed = key.and_then(|key| {
+                        self.pressed_buttons
+                            .iter()
+                            .position(|&pressed| pressed == (which, key))
+                    });
+                    if let (Some(key), Some(pressed)) = (key, pressed) {
+                        self.pressed_buttons.remove(pressed);
+                        self.release(keys, key);
+                    }
                 }
-                Event::KeyUp {
-                    scancode: Some(Scancode::X),
-                    ..
-                } => {
-                    keys[0] = false;
-                }
-                Event::KeyUp {
-                    scancode: Some(Scancode::C),
-                    ..
-                } => {
-                    keys[11] = false;
-                }
-                Event::KeyUp {
-                    scancode: Some(Scancode::V),
-                    ..
-                } => {
-                    keys[15] = false;
-                }
-
                 _ => {}
             }
         }
-        true
+        commands
+    }
+}
+
+fn save_state_slot(scancode: Scancode) -> Option<u8> {
+    match scancode {
+        Scancode::F1 => Some(1),
+        Scancode::F2 => Some(2),
+        Scancode::F3 => Some(3),
+        Scancode::F4 => Some(4),
+        Scancode::F5 => Some(5),
+        Scancode::F6 => Some(6),
+        Scancode::F7 => Some(7),
+        Scancode::F8 => Some(8),
+        Scancode::F9 => Some(9),
+        _ => None,
     }
 }
@@ -0,0 +1,198 @@
+use crate::error::StateError;
+
+// save states are a small header followed by the machine state and a checksum:
+//
+//   magic "R8ST" | version: u16 | payload length: u32 | payload | crc32 of the payload: u32
+//
+// everything is little endian. the payload layout is owned by Chip8::save_state, and any
+// change to it has to bump VERSION.
+
+const MAGIC: &[u8; 4] = b"R8ST";
+pub const VERSION: u16 = 1;
+const HEADER_LEN: usize = MAGIC.len() + 2 + 4;
+
+pub(crate) struct StateWriter {
+    payload: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Self {
+            payload: Vec::new(),
+        }
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.payload.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.payload.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.payload.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.payload.extend_from_slice(&value.to_le_bytes());
+    }
+
+    // length prefixed, for the buffers whose size depends on the platform or display mode
+    pub fn bytes(&mut self, value: &[u8]) {
+        self.u32(value.len() as u32);
+        self.payload.extend_from_slice(value);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        let mut state = Vec::with_capacity(HEADER_LEN + self.payload.len() + 4);
+        state.extend_from_slice(MAGIC);
+        state.extend_from_slice(&VERSION.to_le_bytes());
+        state.extend_from_slice(&(self.payload.len() as u32).to_le_bytes());
+        state.extend_from_slice(&self.payload);
+        state.extend_from_slice(&crc32(&self.payload).to_le_bytes());
+        state
+    }
+}
+
+pub(crate) struct StateReader<'a> {
+    payload: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    // checks the header and checksum before handing out any of the payload
+    pub fn open(state: &'a [u8]) -> Result<Self, StateError> {
+        if state.len() < HEADER_LEN || &state[..MAGIC.len()] != MAGIC {
+            return Err(StateError::NotAState);
+        }
+        let version = u16::from_le_bytes([state[4], state[5]]);
+        if version != VERSION {
+            return Err(StateError::UnsupportedVersion { version });
+        }
+        let length = u32::from_le_bytes([state[6], state[7], state[8], state[9]]) as usize;
+        if state.len() != HEADER_LEN + length + 4 {
+            return Err(StateError::Truncated);
+        }
+
+        let payload = &state[HEADER_LEN..HEADER_LEN + length];
+        let checksum = &state[HEADER_LEN + length..];
+        if crc32(payload).to_le_bytes() != checksum {
+            return Err(StateError::ChecksumMismatch);
+        }
+
+        Ok(Self {
+            payload,
+            position: 0,
+        })
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], StateError> {
+        let bytes = self
+            .payload
+            .get(self.position..self.position + count)
+            .ok_or(StateError::Truncated)?;
+        self.position += count;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, StateError> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn bytes(&mut self) -> Result<&'a [u8], StateError> {
+        let length = self.u32()? as usize;
+        self.take(length)
+    }
+
+    // fixed size fields, the length has to match exactly
+    pub fn array<const N: usize>(&mut self, field: &'static str) -> Result<[u8; N], StateError> {
+        self.bytes()?
+            .try_into()
+            .map_err(|_| StateError::InvalidField { field })
+    }
+}
+
+// the usual IEEE crc32, bit by bit since save states are small and rare
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFFFFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB88320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        writer.u8(7);
+        writer.u16(0x1234);
+        writer.bytes(&[1, 2, 3]);
+        writer.finish()
+    }
+
+    #[test]
+    fn fields_read_back_as_written() {
+        let state = state();
+        let mut reader = StateReader::open(&state).unwrap();
+        assert_eq!(reader.u8(), Ok(7));
+        assert_eq!(reader.u16(), Ok(0x1234));
+        assert_eq!(reader.bytes(), Ok(&[1, 2, 3][..]));
+        assert_eq!(reader.u8(), Err(StateError::Truncated));
+    }
+
+    #[test]
+    fn damaged_states_are_refused() {
+        let open = |edit: fn(&mut Vec<u8>)| {
+            let mut state = state();
+            edit(&mut state);
+            StateReader::open(&state).err()
+        };
+        assert_eq!(open(|state| state[0] = b'X'), Some(StateError::NotAState));
+        assert_eq!(open(|state| state.truncate(6)), Some(StateError::NotAState));
+        assert_eq!(
+            open(|state| state[4] = 2),
+            Some(StateError::UnsupportedVersion { version: 2 })
+        );
+        assert_eq!(
+            open(|state| state.truncate(14)),
+            Some(StateError::Truncated)
+        );
+        assert_eq!(open(|state| state.push(0)), Some(StateError::Truncated));
+        assert_eq!(
+            open(|state| state[HEADER_LEN] ^= 1),
+            Some(StateError::ChecksumMismatch)
+        );
+        assert_eq!(
+            open(|state| *state.last_mut().unwrap() ^= 1),
+            Some(StateError::ChecksumMismatch)
+        );
+    }
+}